@@ -46,6 +46,8 @@ interface NotesRepository {
 
     suspend fun replaceNote(note: FfiNote): Result<Unit>
 
+    suspend fun toggleTask(noteId: String, offset: Int): Result<FfiNote>
+
     suspend fun deleteNote(id: String): Result<Unit>
 
     suspend fun deleteNotes(ids: List<String>): Result<Unit>
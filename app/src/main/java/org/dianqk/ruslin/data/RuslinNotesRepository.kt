@@ -126,6 +126,12 @@ class RuslinNotesRepository @Inject constructor(
                 .onSuccess { _notesChangedManually.emit(Unit) }
         }
 
+    override suspend fun toggleTask(noteId: String, offset: Int): Result<FfiNote> =
+        withContext(ioDispatcher) {
+            kotlin.runCatching { data.toggleTask(noteId = noteId, offset = offset) }
+                .onSuccess { _notesChangedManually.emit(Unit) }
+        }
+
     override suspend fun deleteNote(id: String): Result<Unit> =
         withContext(ioDispatcher) {
             kotlin.runCatching { data.deleteNote(id) }
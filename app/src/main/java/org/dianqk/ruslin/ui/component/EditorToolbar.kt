@@ -25,6 +25,8 @@ import androidx.compose.ui.window.PopupProperties
 import org.dianqk.mdrender.MarkdownVisualTransformation
 import org.dianqk.ruslin.R
 import org.dianqk.ruslin.ui.page.note_detail.SavedResource
+import uniffi.ruslin.FormatCommand
+import uniffi.ruslin.formatMarkdown
 
 sealed class MarkdownInsertTagType {
     class Heading(val level: Int) : MarkdownInsertTagType()
@@ -37,6 +39,12 @@ sealed class MarkdownInsertTagType {
 
     object Quote : MarkdownInsertTagType()
 
+    object InlineCode : MarkdownInsertTagType()
+
+    object TaskList : MarkdownInsertTagType()
+
+    object CodeBlock : MarkdownInsertTagType()
+
     class Image(val resourceId: String, val filename: String, val isImage: Boolean) :
         MarkdownInsertTagType()
 
@@ -45,116 +53,64 @@ sealed class MarkdownInsertTagType {
 fun MarkdownInsertTagType.Heading.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, " ")
-    builder.insert(textFieldValue.selection.end, "#".repeat(level))
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + level + 1,
-            end = textFieldValue.selection.end + level + 1,
-        )
-    )
-}
+): TextFieldValue = textFieldValue.format(FormatCommand.Heading(level = level))
 
 fun MarkdownInsertTagType.Bold.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, "**")
-    builder.insert(textFieldValue.selection.start, "**")
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + 2,
-            end = textFieldValue.selection.end + 2,
-        )
-    )
-}
+): TextFieldValue = textFieldValue.format(FormatCommand.Bold)
 
 fun MarkdownInsertTagType.Italic.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, "*")
-    builder.insert(textFieldValue.selection.start, "*")
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + 1,
-            end = textFieldValue.selection.end + 1,
-        )
-    )
-}
+): TextFieldValue = textFieldValue.format(FormatCommand.Italic)
 
 fun MarkdownInsertTagType.ListBulleted.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, "- ")
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + 2,
-            end = textFieldValue.selection.end + 2,
-        )
-    )
-}
+): TextFieldValue = textFieldValue.format(FormatCommand.BulletList)
 
 fun MarkdownInsertTagType.ListNumbered.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, "1. ")
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + 3,
-            end = textFieldValue.selection.end + 3,
-        )
-    )
-}
+): TextFieldValue = textFieldValue.format(FormatCommand.NumberedList)
 
 fun MarkdownInsertTagType.Strikethrough.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, "~~")
-    builder.insert(textFieldValue.selection.start, "~~")
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + 2,
-            end = textFieldValue.selection.end + 2,
-        )
-    )
-}
+): TextFieldValue = textFieldValue.format(FormatCommand.Strikethrough)
 
 fun MarkdownInsertTagType.Quote.insert(
     textFieldValue: TextFieldValue,
     markdownVisualTransformation: MarkdownVisualTransformation
-): TextFieldValue {
-    val text = textFieldValue.text
-    val builder = StringBuilder(text)
-    builder.insert(textFieldValue.selection.end, "> ")
-    return textFieldValue.copy(
-        text = builder.toString(),
-        selection = TextRange(
-            start = textFieldValue.selection.start + 2,
-            end = textFieldValue.selection.end + 2,
-        )
+): TextFieldValue = textFieldValue.format(FormatCommand.Quote)
+
+fun MarkdownInsertTagType.InlineCode.insert(
+    textFieldValue: TextFieldValue,
+    markdownVisualTransformation: MarkdownVisualTransformation
+): TextFieldValue = textFieldValue.format(FormatCommand.InlineCode)
+
+fun MarkdownInsertTagType.TaskList.insert(
+    textFieldValue: TextFieldValue,
+    markdownVisualTransformation: MarkdownVisualTransformation
+): TextFieldValue = textFieldValue.format(FormatCommand.TaskList)
+
+fun MarkdownInsertTagType.CodeBlock.insert(
+    textFieldValue: TextFieldValue,
+    markdownVisualTransformation: MarkdownVisualTransformation
+): TextFieldValue = textFieldValue.format(FormatCommand.CodeBlock)
+
+// Toggles the format of the selection, removing the markup if it's already applied.
+private fun TextFieldValue.format(command: FormatCommand): TextFieldValue {
+    val edit = formatMarkdown(
+        text = text,
+        selectionStart = selection.min,
+        selectionEnd = selection.max,
+        command = command
+    ) ?: return this
+    return copy(
+        text = edit.text,
+        selection = TextRange(start = edit.selectionStart, end = edit.selectionEnd)
     )
 }
 
@@ -276,6 +232,15 @@ fun EditorToolbar(
             IconButton(onClick = { onInsertMarkdownTag(MarkdownInsertTagType.Quote) }) {
                 Icon(Icons.Default.FormatQuote, contentDescription = null)
             }
+            IconButton(onClick = { onInsertMarkdownTag(MarkdownInsertTagType.TaskList) }) {
+                Icon(Icons.Default.Checklist, contentDescription = null)
+            }
+            IconButton(onClick = { onInsertMarkdownTag(MarkdownInsertTagType.InlineCode) }) {
+                Icon(Icons.Default.Code, contentDescription = null)
+            }
+            IconButton(onClick = { onInsertMarkdownTag(MarkdownInsertTagType.CodeBlock) }) {
+                Icon(Icons.Default.DataObject, contentDescription = null)
+            }
             IconButton(onClick = {
                 launcher.launch("*/*")
             }) {
@@ -1,11 +1,13 @@
 package org.dianqk.ruslin.ui.component
 
+import android.annotation.SuppressLint
 import android.content.ActivityNotFoundException
 import android.content.Context
 import android.content.Intent
 import android.content.res.AssetManager
 import android.net.Uri
 import android.util.Log
+import android.webkit.JavascriptInterface
 import android.webkit.WebResourceRequest
 import android.webkit.WebResourceResponse
 import android.webkit.WebView
@@ -16,8 +18,10 @@ import androidx.compose.foundation.clickable
 import androidx.compose.foundation.layout.fillMaxSize
 import androidx.compose.material3.MaterialTheme
 import androidx.compose.runtime.Composable
+import androidx.compose.runtime.getValue
 import androidx.compose.runtime.remember
 import androidx.compose.runtime.rememberCoroutineScope
+import androidx.compose.runtime.rememberUpdatedState
 import androidx.compose.ui.Modifier
 import androidx.compose.ui.graphics.toArgb
 import androidx.compose.ui.platform.LocalContext
@@ -68,18 +72,22 @@ class MarkdownRichTextViewModel @Inject constructor(
 
 }
 
+// JavaScript only runs the script of the preview, see its Content-Security-Policy.
+@SuppressLint("SetJavaScriptEnabled")
 @Composable
 fun MarkdownRichText(
     modifier: Modifier = Modifier,
     viewModel: MarkdownRichTextViewModel = hiltViewModel(),
     htmlBodyText: String,
     navigateToNote: (String) -> Unit,
+    onToggleTask: (Int) -> Unit = {},
 ) {
     val webViewState = rememberWebViewStateWithHTMLData(
         data = htmlBodyText,
     )
     val context = LocalContext.current
     val scope = rememberCoroutineScope()
+    val currentOnToggleTask by rememberUpdatedState(onToggleTask)
 
     val client = remember {
         LocalContentWebViewClient(
@@ -157,12 +165,31 @@ fun MarkdownRichText(
             val webViewSettings = webView.settings
             webViewSettings.allowFileAccess = false
             webViewSettings.allowContentAccess = false
+            webViewSettings.javaScriptEnabled = true
+            webView.addJavascriptInterface(
+                TaskListBridge(webView) { offset -> currentOnToggleTask(offset) },
+                "ruslin"
+            )
             webView.setBackgroundColor(backgroundColor.toArgb())
         },
         client = client
     )
 }
 
+/**
+ * Receives the checkboxes of task lists checked in the preview, see `data-task-offset`.
+ */
+private class TaskListBridge(
+    private val view: WebView,
+    private val onToggleTask: (Int) -> Unit
+) {
+    @JavascriptInterface
+    fun toggleTask(offset: Int) {
+        // Called on a background thread of the WebView.
+        view.post { onToggleTask(offset) }
+    }
+}
+
 private class LocalContentWebViewClient(
     context: Context,
     getResource: (String) -> Result<LocalResource>,
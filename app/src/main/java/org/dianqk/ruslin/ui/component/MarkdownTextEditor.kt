@@ -16,6 +16,12 @@ import androidx.compose.runtime.remember
 import androidx.compose.runtime.setValue
 import androidx.compose.ui.Modifier
 import androidx.compose.ui.graphics.Color
+import androidx.compose.ui.input.key.Key
+import androidx.compose.ui.input.key.KeyEventType
+import androidx.compose.ui.input.key.isShiftPressed
+import androidx.compose.ui.input.key.key
+import androidx.compose.ui.input.key.onPreviewKeyEvent
+import androidx.compose.ui.input.key.type
 import androidx.compose.ui.res.stringResource
 import androidx.compose.ui.text.TextRange
 import androidx.compose.ui.text.TextStyle
@@ -25,6 +31,8 @@ import org.dianqk.mdrender.MarkdownTheme
 import org.dianqk.mdrender.MarkdownVisualTransformation
 import org.dianqk.ruslin.R
 import org.dianqk.ruslin.ui.page.note_detail.SavedResource
+import uniffi.ruslin.handleNewline
+import uniffi.ruslin.indent
 
 @OptIn(ExperimentalMaterial3Api::class, ExperimentalLayoutApi::class)
 @Composable
@@ -70,78 +78,48 @@ fun MarkdownTextEditor(
 
     TextField(
         modifier = modifier
-            .fillMaxWidth(),
+            .fillMaxWidth()
+            .onPreviewKeyEvent { event ->
+                if (event.key != Key.Tab || event.type != KeyEventType.KeyDown) {
+                    return@onPreviewKeyEvent false
+                }
+                val edit = indent(
+                    text = textFieldValueState.text,
+                    selectionStart = textFieldValueState.selection.min,
+                    selectionEnd = textFieldValueState.selection.max,
+                    outdent = event.isShiftPressed
+                ) ?: return@onPreviewKeyEvent false
+                textFieldValueState = textFieldValueState.copy(
+                    text = edit.text,
+                    selection = TextRange(start = edit.selectionStart, end = edit.selectionEnd)
+                )
+                lastTextValue = edit.text
+                onValueChange(edit.text)
+                visualTransformation.invalid()
+                true
+            },
         value = textFieldValue,
         onValueChange = { newTextFieldValueState ->
             val stringChangedSinceLastInvocation = lastTextValue != newTextFieldValueState.text
             var fixedNewTextFieldValueState = newTextFieldValueState
 
             if (stringChangedSinceLastInvocation) {
-                var text = newTextFieldValueState.text
                 val enterNewLineAfterContent =
                     (textFieldValueState.selection.start == textFieldValueState.selection.end)
                             && (newTextFieldValueState.selection.start == newTextFieldValueState.selection.end)
                             && (textFieldValueState.selection.start + 1 == newTextFieldValueState.selection.start)
                             && (newTextFieldValueState.text[newTextFieldValueState.selection.end - 1] == '\n')
                 if (enterNewLineAfterContent) {
-                    val newLineIndex = newTextFieldValueState.selection.end - 1
-                    visualTransformation.matchTag(
-                        index = newLineIndex,
-                        onMatchUnOrderList = {
-                            if (newLineIndex < 1) {
-                                return@matchTag
-                            }
-                            // ignore
-                            if (text[newLineIndex - 1] == '\n') {
-                                return@matchTag
-                            }
-                            val builder = StringBuilder(text)
-                            val selectOffset: Int
-                            // delete
-                            if (newLineIndex >= 2 && text[newLineIndex - 1] == ' ' && text[newLineIndex - 2] == '-') {
-                                selectOffset = -3
-                                builder.delete(newLineIndex - 3, newLineIndex)
-                            } else { // insert
-                                selectOffset = 2
-                                builder.insert(newTextFieldValueState.selection.end, "- ")
-                            }
-                            text = builder.toString()
-                            fixedNewTextFieldValueState = newTextFieldValueState.copy(
-                                text = text,
-                                selection = TextRange(
-                                    start = newTextFieldValueState.selection.start + selectOffset,
-                                    end = newTextFieldValueState.selection.end + selectOffset
-                                )
-                            )
-                        },
-                        onMatchOrderList = {
-                            if (newLineIndex < 1) {
-                                return@matchTag
-                            }
-                            // ignore
-                            if (text[newLineIndex - 1] == '\n') {
-                                return@matchTag
-                            }
-                            val builder = StringBuilder(text)
-                            val selectOffset: Int
-                            // delete
-                            if (newLineIndex >= 2 && text[newLineIndex - 1] == ' ' && text[newLineIndex - 2] == '.') {
-                                selectOffset = -4
-                                builder.delete(newLineIndex - 4, newLineIndex)
-                            } else { // insert
-                                selectOffset = 3
-                                builder.insert(newTextFieldValueState.selection.end, "1. ")
-                            }
-                            text = builder.toString()
-                            fixedNewTextFieldValueState = newTextFieldValueState.copy(
-                                text = text,
-                                selection = TextRange(
-                                    start = newTextFieldValueState.selection.start + selectOffset,
-                                    end = newTextFieldValueState.selection.end + selectOffset
-                                )
-                            )
-                        }
-                    )
+                    // Continues or ends the list item or quote, based on the text before Enter.
+                    handleNewline(
+                        text = textFieldValueState.text,
+                        cursor = textFieldValueState.selection.start
+                    )?.let { edit ->
+                        fixedNewTextFieldValueState = newTextFieldValueState.copy(
+                            text = edit.text,
+                            selection = TextRange(start = edit.selectionStart, end = edit.selectionEnd)
+                        )
+                    }
                 }
             }
             textFieldValueState = fixedNewTextFieldValueState
@@ -204,6 +182,21 @@ fun MarkdownTextEditor(
                 markdownVisualTransformation = visualTransformation
             )
 
+            is MarkdownInsertTagType.InlineCode -> tagType.insert(
+                textFieldValue = textFieldValueState,
+                markdownVisualTransformation = visualTransformation
+            )
+
+            is MarkdownInsertTagType.TaskList -> tagType.insert(
+                textFieldValue = textFieldValueState,
+                markdownVisualTransformation = visualTransformation
+            )
+
+            is MarkdownInsertTagType.CodeBlock -> tagType.insert(
+                textFieldValue = textFieldValueState,
+                markdownVisualTransformation = visualTransformation
+            )
+
             is MarkdownInsertTagType.Image -> tagType.insert(
                 textFieldValue = textFieldValueState,
                 markdownVisualTransformation = visualTransformation
@@ -82,7 +82,8 @@ fun NoteDetailPage(
             navigateToNote = { noteId ->
                 viewModel.saveNote()
                 navigateToNote(noteId)
-            })
+            },
+            onToggleTask = viewModel::toggleTask)
         NoteEditor(
             modifier = Modifier
                 .padding(innerPadding)
@@ -21,6 +21,7 @@ import org.dianqk.ruslin.ui.RuslinDestinationsArgs
 import uniffi.ruslin.FfiNote
 import java.io.FileOutputStream
 import java.io.OutputStream
+import java.util.UUID
 import javax.inject.Inject
 
 data class NoteDetailUiState(
@@ -62,6 +63,12 @@ class NoteDetailViewModel @Inject constructor(
     }
 
     fun saveNote() {
+        viewModelScope.launch {
+            writeNote()
+        }
+    }
+
+    private suspend fun writeNote() {
         if (noteId != null && note == null) {
             Log.w(TAG, "note note loaded yet")
             return
@@ -79,9 +86,25 @@ class NoteDetailViewModel @Inject constructor(
         } else {
             note = notesRepository.newNote(parentId = folderId, title = title, body = body)
         }
+        notesRepository.replaceNote(note!!)
+        edited = false
+    }
+
+    fun toggleTask(offset: Int) {
         viewModelScope.launch {
-            notesRepository.replaceNote(note!!)
-            edited = false
+            // The offset is in the previewed body, which may not be saved yet.
+            writeNote()
+            val id = note?.id ?: return@launch
+            notesRepository.toggleTask(noteId = id, offset = offset)
+                .onSuccess { toggledNote ->
+                    note = toggledNote
+                    _uiState.update {
+                        it.copy(body = toggledNote.body)
+                    }
+                }
+                .onFailure { e ->
+                    Log.e(TAG, "toggle task failed: $e")
+                }
         }
     }
 
@@ -175,6 +198,8 @@ class NoteDetailViewModel @Inject constructor(
         val htmlDirAttribute = uiState.value.textDirectionPreference.toHtmlDirAttribute()
         viewModelScope.launch {
             withContext(Dispatchers.IO) {
+                // Only the script below may run, not scripts in the HTML of the note.
+                val scriptNonce = UUID.randomUUID().toString()
                 // TODO: Adapted to m3 theme, already adapted to the background color
                 val previewHtml = buildString {
                     append(
@@ -184,6 +209,7 @@ class NoteDetailViewModel @Inject constructor(
                                     <head>
                                         <meta charset="UTF-8">
                                         <meta name="viewport" content="width=device-width, initial-scale=1.0, user-scalable=no">
+                                        <meta http-equiv="Content-Security-Policy" content="script-src 'nonce-$scriptNonce'">
                                         <link rel="stylesheet" href="ruslin-assets:///github-markdown.min.css">
                                         <style>
                                             @media (prefers-color-scheme:dark) {
@@ -306,7 +332,20 @@ class NoteDetailViewModel @Inject constructor(
                             """.trimIndent()
                     )
                     append(notesRepository.parseMarkdownToPreviewHtml(body))
-                    append("</article></html>")
+                    append("</article>")
+                    append(
+                        """
+                                <script nonce="$scriptNonce">
+                                    document.addEventListener("change", function (event) {
+                                        var offset = event.target.getAttribute("data-task-offset");
+                                        if (offset !== null) {
+                                            ruslin.toggleTask(Number(offset));
+                                        }
+                                    });
+                                </script>
+                            """.trimIndent()
+                    )
+                    append("</html>")
                 }
                 _uiState.update {
                     it.copy(previewHtml = previewHtml)
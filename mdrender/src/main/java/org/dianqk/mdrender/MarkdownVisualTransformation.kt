@@ -1,6 +1,5 @@
 package org.dianqk.mdrender
 
-import android.util.Range
 import androidx.compose.material3.ColorScheme
 import androidx.compose.material3.Typography
 import androidx.compose.ui.graphics.Color
@@ -27,14 +26,9 @@ class MarkdownVisualTransformation(private val theme: MarkdownTheme = MarkdownDe
     var cachedParsedTagRanges: ParsedTagRanges = ParsedTagRanges(emptyList())
         private set
 
-    var unorderedListRanges: MutableList<Range<Int>> = mutableListOf()
-    var orderedListRanges: MutableList<Range<Int>> = mutableListOf()
-
     fun invalid() {
         cachedRenderText = null
         cachedParsedTagRanges = ParsedTagRanges(emptyList())
-        unorderedListRanges.clear()
-        orderedListRanges.clear()
     }
 
     fun parse(text: AnnotatedString): ParsedTagRanges {
@@ -53,7 +47,7 @@ class MarkdownVisualTransformation(private val theme: MarkdownTheme = MarkdownDe
                 is MarkdownTagRange.Strikethrough -> tagRange.render(builder, theme)
                 is MarkdownTagRange.InlineCode -> tagRange.render(builder, theme)
                 is MarkdownTagRange.ListItem -> tagRange.render(builder, theme)
-                is MarkdownTagRange.MList -> tagRange.render(this)
+                is MarkdownTagRange.MList -> {}
                 is MarkdownTagRange.Paragraph -> {}
                 is MarkdownTagRange.Link -> tagRange.render(builder, theme)
                 is MarkdownTagRange.Image -> tagRange.render(builder, theme)
@@ -61,30 +55,17 @@ class MarkdownVisualTransformation(private val theme: MarkdownTheme = MarkdownDe
                 is MarkdownTagRange.BlockQuote -> tagRange.render(builder, theme)
                 is MarkdownTagRange.TaskListMarker -> tagRange.render(builder, theme)
                 is MarkdownTagRange.CodeBlock -> tagRange.render(builder, theme)
+                is MarkdownTagRange.Math -> tagRange.render(builder, theme)
+                is MarkdownTagRange.Html -> tagRange.render(builder, theme)
+                is MarkdownTagRange.FootnoteReference -> tagRange.render(builder, theme)
+                is MarkdownTagRange.FootnoteDefinition -> tagRange.render(builder, theme)
+                is MarkdownTagRange.Autolink -> tagRange.render(builder, theme)
+                is MarkdownTagRange.ReferenceLink -> tagRange.render(builder, theme)
             }
         }
         return builder.toAnnotatedString()
     }
 
-    fun matchTag(
-        index: Int,
-        onMatchUnOrderList: () -> Unit,
-        onMatchOrderList: () -> Unit
-    ) {
-        for (unorderedListRange in unorderedListRanges) {
-            if (unorderedListRange.contains(index)) {
-                onMatchUnOrderList()
-                return
-            }
-        }
-        for (orderedListRange in orderedListRanges) {
-            if (orderedListRange.contains(index)) {
-                onMatchOrderList()
-                return
-            }
-        }
-    }
-
     override fun filter(text: AnnotatedString): TransformedText {
         if (text.isEmpty()) {
             return TransformedText(text = text, offsetMapping = OffsetMapping.Identity)
@@ -158,7 +139,11 @@ data class MarkdownTheme(
     val blockQuoteTag: SpanStyle = SpanStyle(),
     val blockQuote: SpanStyle = SpanStyle(),
     val taskListMarkerTag: SpanStyle = SpanStyle(fontFamily = FontFamily.Monospace),
-    val codeBlock: SpanStyle = SpanStyle(fontFamily = FontFamily.Monospace)
+    val codeBlock: SpanStyle = SpanStyle(fontFamily = FontFamily.Monospace),
+    val math: SpanStyle = SpanStyle(fontFamily = FontFamily.Monospace),
+    val mathTag: SpanStyle = SpanStyle(),
+    val html: SpanStyle = SpanStyle(fontFamily = FontFamily.Monospace),
+    val footnoteTag: SpanStyle = SpanStyle()
 ) {
 
     companion object {
@@ -177,7 +162,11 @@ data class MarkdownTheme(
             ruleTag = MarkdownDefaultTheme.ruleTag.copy(color = colorScheme.primary),
             blockQuoteTag = MarkdownDefaultTheme.blockQuoteTag.copy(color = colorScheme.primary),
             taskListMarkerTag = MarkdownDefaultTheme.taskListMarkerTag.copy(color = colorScheme.primary),
-            codeBlock = MarkdownDefaultTheme.codeBlock.copy(color = colorScheme.primary)
+            codeBlock = MarkdownDefaultTheme.codeBlock.copy(color = colorScheme.primary),
+            math = MarkdownDefaultTheme.math.copy(color = colorScheme.primary),
+            mathTag = MarkdownDefaultTheme.mathTag.copy(color = colorScheme.primary),
+            html = MarkdownDefaultTheme.html.copy(color = Color.Gray),
+            footnoteTag = MarkdownDefaultTheme.footnoteTag.copy(color = colorScheme.primary)
         )
     }
 }
@@ -260,46 +249,32 @@ private fun MarkdownTagRange.ListItem.render(
 //    builder.addStyle(SpanStyle(background = Color.Black.copy(alpha = 0.3f)), start = start + (if (ordered) 3 else 2), end = end)
 }
 
-private fun MarkdownTagRange.MList.render(
-    transformation: MarkdownVisualTransformation,
-) {
-    if (order == 0) {
-        transformation.unorderedListRanges.add(Range(start, end))
-    } else {
-        transformation.orderedListRanges.add(Range(start, end))
-    }
-}
-
 private fun MarkdownTagRange.Link.render(
     builder: AnnotatedString.Builder,
     theme: MarkdownTheme
 ) {
-    // []
-    builder.addStyle(theme.linkTag, start, start + 1)
-    builder.addStyle(theme.linkTag, urlOffset - 2, urlOffset - 1)
+    // [
+    builder.addStyle(theme.linkTag, start, textStart)
 
-    // ()
-    builder.addStyle(theme.linkTag, urlOffset - 1, urlOffset)
-    builder.addStyle(theme.linkTag, end - 1, end)
+    // ](, with a < around the url
+    builder.addStyle(theme.linkTag, textEnd, urlStart)
+
+    // title and )
+    builder.addStyle(theme.linkTag, urlEnd, end)
 
     // url
-//    builder.addStyle(SpanStyle(color = colorScheme.primary), urlOffset, end - 1);
+//    builder.addStyle(SpanStyle(color = colorScheme.primary), urlStart, urlEnd);
 }
 
 private fun MarkdownTagRange.Image.render(
     builder: AnnotatedString.Builder,
     theme: MarkdownTheme
 ) {
-    // []
-    builder.addStyle(theme.imageTag, start, start + 2)
-    builder.addStyle(theme.imageTag, urlOffset - 2, urlOffset - 1)
-
-    // ()
-    builder.addStyle(theme.imageTag, urlOffset - 1, urlOffset)
-    builder.addStyle(theme.imageTag, end - 1, end)
+    // ![
+    builder.addStyle(theme.imageTag, start, textStart)
 
-    // url
-    builder.addStyle(theme.imageTag, urlOffset, end - 1)
+    // ](url "title")
+    builder.addStyle(theme.imageTag, textEnd, end)
 }
 
 private fun MarkdownTagRange.Rule.render(
@@ -346,3 +321,60 @@ private fun MarkdownTagRange.CodeBlock.render(
         end
     )
 }
+
+private fun MarkdownTagRange.Math.render(
+    builder: AnnotatedString.Builder,
+    theme: MarkdownTheme
+) {
+    val delimiterLength = if (display) 2 else 1
+    builder.addStyle(theme.math, start, end)
+    builder.addStyle(theme.mathTag, start, start + delimiterLength)
+    builder.addStyle(theme.mathTag, end - delimiterLength, end)
+}
+
+private fun MarkdownTagRange.Html.render(
+    builder: AnnotatedString.Builder,
+    theme: MarkdownTheme
+) {
+    builder.addStyle(theme.html, start, end)
+}
+
+private fun MarkdownTagRange.FootnoteReference.render(
+    builder: AnnotatedString.Builder,
+    theme: MarkdownTheme
+) {
+    builder.addStyle(theme.footnoteTag, start, end)
+}
+
+private fun MarkdownTagRange.FootnoteDefinition.render(
+    builder: AnnotatedString.Builder,
+    theme: MarkdownTheme
+) {
+    // [^label]:
+    builder.addStyle(theme.footnoteTag, start, labelEnd)
+}
+
+private fun MarkdownTagRange.Autolink.render(
+    builder: AnnotatedString.Builder,
+    theme: MarkdownTheme
+) {
+    // <>
+    builder.addStyle(theme.linkTag, start, start + 1)
+    builder.addStyle(theme.linkTag, end - 1, end)
+}
+
+private fun MarkdownTagRange.ReferenceLink.render(
+    builder: AnnotatedString.Builder,
+    theme: MarkdownTheme
+) {
+    val tag = if (isImage) theme.imageTag else theme.linkTag
+    val textStart = start + (if (isImage) 2 else 1)
+    // [ or ![
+    builder.addStyle(tag, start, textStart)
+    if (labelStart > textStart) {
+        // ][label
+        builder.addStyle(tag, labelStart - 2, labelEnd)
+    }
+    // ] or ][]
+    builder.addStyle(tag, labelEnd, end)
+}
@@ -0,0 +1,493 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use crate::dom::{parse_html, Element, Node};
+use crate::download::Download;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipMode {
+    /// Only the main content, like a reader view.
+    Simplified,
+    FullPage,
+    /// A link to the page with its description.
+    Bookmark,
+}
+
+#[derive(Default)]
+pub struct PageMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    /// The `<base>` URL, which relative URLs are resolved against instead of the page URL.
+    pub base_url: Option<String>,
+}
+
+/// Decodes the page with the charset of the `Content-Type` header, or else the one its
+/// `<meta>` tag declares, falling back to UTF-8. A byte order mark overrides both, like in
+/// browsers.
+pub fn decode_page(page: &Download) -> String {
+    let encoding = page
+        .charset
+        .as_deref()
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .or_else(|| meta_charset(&page.bytes))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(&page.bytes);
+    text.into_owned()
+}
+
+/// Browsers look for the charset declaration in the first 1024 bytes.
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+    let document = parse_html(&head);
+    let mut label = None;
+    document.descendants(&mut |element| {
+        if element.name != "meta" || label.is_some() {
+            return;
+        }
+        label = element
+            .attribute("charset")
+            .map(str::to_string)
+            .or_else(|| {
+                let http_equiv = element.attribute("http-equiv")?;
+                if !http_equiv.eq_ignore_ascii_case("content-type") {
+                    return None;
+                }
+                let content = element.attribute("content")?.to_ascii_lowercase();
+                let (_, charset) = content.split_once("charset=")?;
+                let charset = charset.split(';').next()?;
+                Some(charset.trim().trim_matches(['"', '\'']).to_string())
+            });
+    });
+    let encoding = Encoding::for_label(label?.trim().as_bytes())?;
+    // A UTF-16 page would start with a byte order mark, the declaration is wrong.
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        Some(UTF_8)
+    } else {
+        Some(encoding)
+    }
+}
+
+/// Reads the title, author and description from the meta tags that sites set for link
+/// previews, falling back to the document itself.
+pub fn metadata(document: &Element) -> PageMetadata {
+    let mut metadata = PageMetadata::default();
+    let mut metas: Vec<(String, String)> = Vec::new();
+    let mut title = None;
+    let mut heading = None;
+    let mut author_link = None;
+    let mut byline = None;
+    document.descendants(&mut |element| match element.name.as_str() {
+        "meta" => {
+            let key = element
+                .attribute("property")
+                .or_else(|| element.attribute("name"));
+            if let (Some(key), Some(content)) = (key, element.attribute("content")) {
+                metas.push((key.trim().to_ascii_lowercase(), collapse(content)));
+            }
+        }
+        "base" if metadata.base_url.is_none() => {
+            metadata.base_url = element
+                .attribute("href")
+                .map(|href| href.trim().to_string());
+        }
+        "title" if title.is_none() => title = Some(collapse(&text(element))),
+        "h1" if heading.is_none() => heading = Some(collapse(&text(element))),
+        "a" if author_link.is_none() && element.attribute("rel") == Some("author") => {
+            author_link = Some(collapse(&text(element)));
+        }
+        _ if byline.is_none() && class_and_id(element).contains("byline") => {
+            byline = Some(collapse(&text(element)));
+        }
+        _ => {}
+    });
+    let meta = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            metas
+                .iter()
+                .find(|(name, content)| name == key && !content.is_empty())
+                .map(|(_, content)| content.clone())
+        })
+    };
+    let non_empty = |text: Option<String>| text.filter(|text| !text.is_empty());
+
+    metadata.title = meta(&["og:title", "twitter:title"])
+        .or(non_empty(title))
+        .or(non_empty(heading));
+    // `article:author` is often a link to the author's profile.
+    metadata.author = meta(&["author", "article:author", "twitter:creator"])
+        .filter(|author| !author.starts_with("http://") && !author.starts_with("https://"))
+        .or(non_empty(author_link))
+        .or(non_empty(byline).filter(|byline| byline.chars().count() < 100));
+    metadata.description = meta(&["og:description", "description", "twitter:description"]);
+    metadata
+}
+
+/// The `<body>`, or the document if it has none.
+pub fn body(document: &Element) -> &Element {
+    let mut body = None;
+    document.descendants(&mut |element| {
+        if element.name == "body" && body.is_none() {
+            body = Some(element);
+        }
+    });
+    body.unwrap_or(document)
+}
+
+/// Returns the elements with the main content of the page, in the spirit of Readability.
+/// Navigation, sidebars, comments and the like are removed, paragraphs score the elements
+/// containing them, and the best scoring one is picked with the related siblings.
+pub fn main_content(document: &mut Element) -> Vec<&Element> {
+    prune(document);
+    let document: &Element = document;
+
+    let mut nodes: Vec<(&Element, Option<usize>)> = Vec::new();
+    collect(document, None, &mut nodes);
+    let mut scores: Vec<Option<f64>> = vec![None; nodes.len()];
+    for &(element, parent) in &nodes {
+        if !is_paragraph(element) {
+            continue;
+        }
+        let text = collapse(&text(element));
+        let length = text.chars().count();
+        if length < 25 {
+            continue;
+        }
+        let commas = text
+            .chars()
+            .filter(|&c| matches!(c, ',' | '，' | '、'))
+            .count();
+        let score = 1.0 + commas as f64 + (length / 100).min(3) as f64;
+        let Some(parent) = parent else {
+            continue;
+        };
+        *scores[parent].get_or_insert_with(|| initial_score(nodes[parent].0)) += score;
+        if let Some(grandparent) = nodes[parent].1 {
+            *scores[grandparent].get_or_insert_with(|| initial_score(nodes[grandparent].0)) +=
+                score / 2.0;
+        }
+    }
+    for (index, score) in scores.iter_mut().enumerate() {
+        if let Some(score) = score {
+            *score *= 1.0 - link_density(nodes[index].0);
+        }
+    }
+
+    let best = scores
+        .iter()
+        .enumerate()
+        .filter_map(|(index, score)| score.map(|score| (index, score)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    let Some((best, best_score)) = best else {
+        return vec![body(document)];
+    };
+    let Some(parent) = nodes[best].1 else {
+        return vec![nodes[best].0];
+    };
+
+    // Content is sometimes split into siblings, e.g. around an image or ad.
+    let threshold = (best_score * 0.2).max(10.0);
+    let mut content = Vec::new();
+    for (index, &(element, element_parent)) in nodes.iter().enumerate() {
+        if element_parent != Some(parent) {
+            continue;
+        }
+        let related = index == best
+            || scores[index].is_some_and(|score| score >= threshold)
+            || (element.name == "p"
+                && collapse(&text(element)).chars().count() > 80
+                && link_density(element) < 0.25);
+        if related {
+            content.push(element);
+        }
+    }
+    content
+}
+
+/// Collects the elements in document order with the index of their parent.
+fn collect<'a>(
+    element: &'a Element,
+    parent: Option<usize>,
+    nodes: &mut Vec<(&'a Element, Option<usize>)>,
+) {
+    for child in element.elements() {
+        let index = nodes.len();
+        nodes.push((child, parent));
+        collect(child, Some(index), nodes);
+    }
+}
+
+const UNLIKELY_CANDIDATES: &[&str] = &[
+    "-ad-",
+    "ad-break",
+    "agegate",
+    "banner",
+    "breadcrumbs",
+    "combx",
+    "comment",
+    "community",
+    "cover-wrap",
+    "disqus",
+    "extra",
+    "footer",
+    "gdpr",
+    "header",
+    "legends",
+    "menu",
+    "pager",
+    "pagination",
+    "popup",
+    "related",
+    "remark",
+    "replies",
+    "rss",
+    "share",
+    "shoutbox",
+    "sidebar",
+    "skyscraper",
+    "social",
+    "sponsor",
+    "supplemental",
+];
+
+const MAYBE_CANDIDATES: &[&str] = &[
+    "and", "article", "body", "column", "content", "main", "shadow",
+];
+
+const POSITIVE_NAMES: &[&str] = &[
+    "article", "blog", "body", "content", "entry", "h-entry", "hentry", "main", "page", "post",
+    "story", "text",
+];
+
+const NEGATIVE_NAMES: &[&str] = &[
+    "banner",
+    "combx",
+    "comment",
+    "com-",
+    "contact",
+    "foot",
+    "masthead",
+    "media",
+    "meta",
+    "outbrain",
+    "promo",
+    "related",
+    "scroll",
+    "share",
+    "shopping",
+    "shoutbox",
+    "sidebar",
+    "skyscraper",
+    "sponsor",
+    "tags",
+    "tool",
+    "widget",
+];
+
+const UNLIKELY_ROLES: &[&str] = &[
+    "alert",
+    "alertdialog",
+    "complementary",
+    "dialog",
+    "menu",
+    "menubar",
+    "navigation",
+];
+
+/// Removes elements that aren't part of the main content.
+fn prune(element: &mut Element) {
+    element.children.retain_mut(|child| {
+        let Node::Element(child) = child else {
+            return true;
+        };
+        if is_unlikely(child) {
+            return false;
+        }
+        prune(child);
+        !is_link_list(child)
+    });
+}
+
+fn is_unlikely(element: &Element) -> bool {
+    if matches!(
+        element.name.as_str(),
+        "aside" | "footer" | "form" | "nav" | "dialog" | "button" | "select" | "iframe"
+    ) {
+        return true;
+    }
+    let style = element
+        .attribute("style")
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+        .replace(' ', "");
+    if element.attribute("hidden").is_some()
+        || element.attribute("aria-hidden") == Some("true")
+        || style.contains("display:none")
+        || style.contains("visibility:hidden")
+    {
+        return true;
+    }
+    if element
+        .attribute("role")
+        .is_some_and(|role| UNLIKELY_ROLES.contains(&role))
+    {
+        return true;
+    }
+    if matches!(
+        element.name.as_str(),
+        "html" | "body" | "a" | "article" | "main"
+    ) {
+        return false;
+    }
+    let names = class_and_id(element);
+    UNLIKELY_CANDIDATES.iter().any(|name| names.contains(name))
+        && !MAYBE_CANDIDATES.iter().any(|name| names.contains(name))
+}
+
+/// Lists and blocks of mostly links, like navigation and tag clouds.
+fn is_link_list(element: &Element) -> bool {
+    if !matches!(
+        element.name.as_str(),
+        "div" | "section" | "ul" | "ol" | "table" | "header"
+    ) {
+        return false;
+    }
+    let mut has_image = false;
+    element.descendants(&mut |element| has_image |= element.name == "img");
+    !has_image && link_density(element) > 0.5
+}
+
+fn initial_score(element: &Element) -> f64 {
+    let tag = match element.name.as_str() {
+        "div" | "article" | "main" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag + class_weight(element)
+}
+
+fn class_weight(element: &Element) -> f64 {
+    let names = class_and_id(element);
+    let mut weight = 0.0;
+    if POSITIVE_NAMES.iter().any(|name| names.contains(name)) {
+        weight += 25.0;
+    }
+    if NEGATIVE_NAMES.iter().any(|name| names.contains(name)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+/// Paragraphs, and divs only used to hold text like them.
+fn is_paragraph(element: &Element) -> bool {
+    match element.name.as_str() {
+        "p" | "pre" => true,
+        "div" => !element.elements().any(|child| {
+            matches!(
+                child.name.as_str(),
+                "div"
+                    | "p"
+                    | "pre"
+                    | "ul"
+                    | "ol"
+                    | "table"
+                    | "blockquote"
+                    | "section"
+                    | "article"
+                    | "h1"
+                    | "h2"
+                    | "h3"
+                    | "h4"
+                    | "h5"
+                    | "h6"
+            )
+        }),
+        _ => false,
+    }
+}
+
+/// The share of the text that is in links.
+fn link_density(element: &Element) -> f64 {
+    let length = text_length(element);
+    if length == 0 {
+        return 0.0;
+    }
+    let mut link_length = 0;
+    element.descendants(&mut |element| {
+        if element.name == "a" {
+            link_length += text_length(element);
+        }
+    });
+    // Nested links are counted twice, which can't make the density too low.
+    (link_length as f64 / length as f64).min(1.0)
+}
+
+fn text_length(element: &Element) -> usize {
+    text(element).chars().filter(|c| !c.is_whitespace()).count()
+}
+
+fn class_and_id(element: &Element) -> String {
+    let class = element.attribute("class").unwrap_or_default();
+    let id = element.attribute("id").unwrap_or_default();
+    format!("{class} {id}").to_lowercase()
+}
+
+fn text(element: &Element) -> String {
+    fn push(element: &Element, text: &mut String) {
+        for child in &element.children {
+            match child {
+                Node::Text(content) => text.push_str(content),
+                Node::Element(child)
+                    if matches!(child.name.as_str(), "script" | "style" | "noscript") => {}
+                Node::Element(child) => push(child, text),
+            }
+        }
+    }
+    let mut text = String::new();
+    push(element, &mut text);
+    text
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(charset: Option<&str>, bytes: &[u8]) -> Download {
+        Download {
+            url: "https://example.com/".to_string(),
+            mime: Some("text/html".to_string()),
+            charset: charset.map(str::to_string),
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn charsets() {
+        let cases: &[(Option<&str>, &[u8], &str)] = &[
+            (None, "<p>café</p>".as_bytes(), "<p>café</p>"),
+            (Some("ISO-8859-1"), b"<p>caf\xe9</p>", "<p>café</p>"),
+            (
+                None,
+                b"<meta charset=\"windows-1252\"><p>\x93caf\xe9\x94</p>",
+                "<meta charset=\"windows-1252\"><p>\u{201c}café\u{201d}</p>",
+            ),
+            (
+                None,
+                b"<meta http-equiv=Content-Type content='text/html; charset=Shift_JIS'>\x93\xfa\x96\x7b",
+                "<meta http-equiv=Content-Type content='text/html; charset=Shift_JIS'>日本",
+            ),
+            // The header wins over the declaration, a byte order mark over both.
+            (Some("utf-8"), b"<meta charset=gbk>caf\xc3\xa9", "<meta charset=gbk>café"),
+            (Some("gbk"), b"\xef\xbb\xbfcaf\xc3\xa9", "café"),
+            (None, b"<meta charset=utf-16>caf\xc3\xa9", "<meta charset=utf-16>café"),
+        ];
+        for (charset, bytes, text) in cases {
+            assert_eq!(decode_page(&page(*charset, bytes)), *text);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+/// Returns the diagram kind of a fenced code block language, or `None` for regular code.
+pub fn diagram_kind(lang: &str) -> Option<&'static str> {
+    match lang.to_ascii_lowercase().as_str() {
+        "mermaid" => Some("mermaid"),
+        "plantuml" | "puml" => Some("plantuml"),
+        "graphviz" | "dot" => Some("graphviz"),
+        _ => None,
+    }
+}
+
+/// Renders the diagram to SVG in Rust, if supported for this kind. Other diagrams are left
+/// to a JS renderer in the preview.
+pub fn render_svg(kind: &str, source: &str) -> Option<String> {
+    match kind {
+        "graphviz" => render_graphviz(source),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "graphviz")]
+fn render_graphviz(source: &str) -> Option<String> {
+    use layout::backends::svg::SVGWriter;
+    use layout::gv::{DotParser, GraphBuilder};
+
+    // The layout code asserts on some inputs it can't handle.
+    std::panic::catch_unwind(|| {
+        let graph = match DotParser::new(source).process() {
+            Ok(graph) => graph,
+            Err(e) => {
+                log::debug!("parse graphviz error: {e}");
+                return None;
+            }
+        };
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual_graph = builder.get();
+        let mut svg = SVGWriter::new();
+        visual_graph.do_it(false, false, false, &mut svg);
+        let svg = svg.finalize();
+        // Drop the XML declaration, the SVG is embedded in HTML.
+        let svg = match svg.find("<svg") {
+            Some(start) => svg[start..].to_string(),
+            None => svg,
+        };
+        Some(svg)
+    })
+    .unwrap_or_else(|_| {
+        log::error!("render graphviz panicked");
+        None
+    })
+}
+
+#[cfg(not(feature = "graphviz"))]
+fn render_graphviz(_source: &str) -> Option<String> {
+    None
+}
@@ -0,0 +1,551 @@
+/// A forgiving HTML parser for pasted and shared pages. It builds a plain element tree and
+/// handles the implied end tags that real pages rely on, but doesn't implement the full HTML5
+/// tree construction algorithm.
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+pub struct Element {
+    /// The lowercase tag name, or empty for the document root.
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    fn new(name: String, attributes: Vec<(String, String)>) -> Self {
+        Self {
+            name,
+            attributes,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// Visits all descendant elements in document order.
+    pub fn descendants<'a>(&'a self, visit: &mut impl FnMut(&'a Element)) {
+        for element in self.elements() {
+            visit(element);
+            element.descendants(visit);
+        }
+    }
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is text up to the matching end tag.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title", "xmp", "noscript"];
+
+/// Elements whose start tag closes an open paragraph.
+const CLOSES_PARAGRAPH: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// The deepest nesting of elements. The tree is walked recursively, so deeper elements are
+/// added as siblings instead.
+const MAX_DEPTH: usize = 512;
+
+const SCOPE_BOUNDARIES: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+pub fn parse_html(html: &str) -> Element {
+    let mut builder = TreeBuilder {
+        stack: vec![Element::new(String::new(), Vec::new())],
+    };
+    let bytes = html.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] != b'<' {
+            let end = html[index..].find('<').map_or(html.len(), |i| index + i);
+            builder.text(decode_entities(&html[index..end]));
+            index = end;
+            continue;
+        }
+        let rest = &html[index..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            index = comment
+                .find("-->")
+                .map_or(html.len(), |i| index + 4 + i + 3);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            index = rest.find('>').map_or(html.len(), |i| index + i + 1);
+        } else if rest.starts_with("</")
+            && bytes.get(index + 2).is_some_and(u8::is_ascii_alphabetic)
+        {
+            let (name, end) = tag_name(html, index + 2);
+            builder.close(&name);
+            index = html[end..].find('>').map_or(html.len(), |i| end + i + 1);
+        } else if bytes.get(index + 1).is_some_and(u8::is_ascii_alphabetic) {
+            let (name, end) = tag_name(html, index + 1);
+            let (attributes, end) = attributes(html, end);
+            index = end;
+            if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                let content_end = find_end_tag(html, index, &name).unwrap_or(html.len());
+                let content = &html[index..content_end];
+                let mut element = Element::new(name, attributes);
+                if !content.is_empty() {
+                    element
+                        .children
+                        .push(Node::Text(match element.name.as_str() {
+                            "textarea" | "title" => decode_entities(content),
+                            _ => content.to_string(),
+                        }));
+                }
+                builder.open(element);
+                builder.close_current();
+                index = html[content_end..]
+                    .find('>')
+                    .map_or(html.len(), |i| content_end + i + 1);
+            } else {
+                builder.open(Element::new(name, attributes));
+            }
+        } else {
+            builder.text("<".to_string());
+            index += 1;
+        }
+    }
+    builder.finish()
+}
+
+struct TreeBuilder {
+    stack: Vec<Element>,
+}
+
+impl TreeBuilder {
+    fn text(&mut self, text: String) {
+        let current = self.stack.last_mut().unwrap();
+        match current.children.last_mut() {
+            Some(Node::Text(previous)) => previous.push_str(&text),
+            _ => current.children.push(Node::Text(text)),
+        }
+    }
+
+    fn open(&mut self, element: Element) {
+        let name = element.name.as_str();
+        if CLOSES_PARAGRAPH.contains(&name) {
+            self.close_in_scope(&["p"], &[]);
+        }
+        match name {
+            "li" => self.close_in_scope(&["li"], &["ol", "ul"]),
+            "dt" | "dd" => self.close_in_scope(&["dt", "dd"], &["dl"]),
+            "tr" => {
+                self.close_in_scope(&["td", "th"], &["tr"]);
+                self.close_in_scope(&["tr"], &["thead", "tbody", "tfoot"]);
+            }
+            "td" | "th" => self.close_in_scope(&["td", "th"], &["tr"]),
+            "thead" | "tbody" | "tfoot" => {
+                self.close_in_scope(&["td", "th"], &["tr"]);
+                self.close_in_scope(&["tr"], &["thead", "tbody", "tfoot"]);
+                self.close_in_scope(&["thead", "tbody", "tfoot"], &[]);
+            }
+            "body" => self.close_in_scope(&["head"], &[]),
+            _ => {}
+        }
+        if VOID_ELEMENTS.contains(&name) {
+            let current = self.stack.last_mut().unwrap();
+            current.children.push(Node::Element(element));
+        } else {
+            // The stack includes the document root.
+            if self.stack.len() > MAX_DEPTH {
+                self.close_current();
+            }
+            self.stack.push(element);
+        }
+    }
+
+    /// Closes the innermost open element named one of `names`, unless a scope boundary or one
+    /// of `boundaries` is open inside it.
+    fn close_in_scope(&mut self, names: &[&str], boundaries: &[&str]) {
+        for index in (1..self.stack.len()).rev() {
+            let name = self.stack[index].name.as_str();
+            if names.contains(&name) {
+                self.pop_to(index);
+                return;
+            }
+            if SCOPE_BOUNDARIES.contains(&name) || boundaries.contains(&name) {
+                return;
+            }
+        }
+    }
+
+    /// Closes the innermost open element with this name and everything opened inside it. End
+    /// tags without an open element are ignored.
+    fn close(&mut self, name: &str) {
+        if let Some(index) = (1..self.stack.len())
+            .rev()
+            .find(|&index| self.stack[index].name == name)
+        {
+            self.pop_to(index);
+        }
+    }
+
+    fn close_current(&mut self) {
+        self.pop_to(self.stack.len() - 1);
+    }
+
+    fn pop_to(&mut self, index: usize) {
+        while self.stack.len() > index {
+            let element = self.stack.pop().unwrap();
+            let parent = self.stack.last_mut().unwrap();
+            parent.children.push(Node::Element(element));
+        }
+    }
+
+    fn finish(mut self) -> Element {
+        self.pop_to(1);
+        self.stack.pop().unwrap()
+    }
+}
+
+fn is_html_whitespace(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n' | b'\r' | b'\x0c')
+}
+
+fn tag_name(html: &str, start: usize) -> (String, usize) {
+    let end = html[start..]
+        .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+        .map_or(html.len(), |i| start + i);
+    (html[start..end].to_ascii_lowercase(), end)
+}
+
+/// Parses the attributes of a start tag, returning them and the offset after the tag.
+fn attributes(html: &str, start: usize) -> (Vec<(String, String)>, usize) {
+    let bytes = html.as_bytes();
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    let mut index = start;
+    loop {
+        while bytes
+            .get(index)
+            .is_some_and(|&c| is_html_whitespace(c) || c == b'/')
+        {
+            index += 1;
+        }
+        match bytes.get(index) {
+            None => return (attributes, index),
+            Some(b'>') => return (attributes, index + 1),
+            _ => {}
+        }
+        let name_end = html[index..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '>' || c == '/')
+            .map_or(html.len(), |i| index + i);
+        // Always advance, e.g. past a stray `=`.
+        let name_end = name_end.max(index + 1);
+        let name = html[index..name_end].to_ascii_lowercase();
+        index = name_end;
+        while bytes.get(index).is_some_and(|&c| is_html_whitespace(c)) {
+            index += 1;
+        }
+        let mut value = String::new();
+        if bytes.get(index) == Some(&b'=') {
+            index += 1;
+            while bytes.get(index).is_some_and(|&c| is_html_whitespace(c)) {
+                index += 1;
+            }
+            let value_end = match bytes.get(index) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    index += 1;
+                    let end = html[index..]
+                        .find(quote as char)
+                        .map_or(html.len(), |i| index + i);
+                    value = decode_entities(&html[index..end]);
+                    (end + 1).min(html.len())
+                }
+                _ => {
+                    let end = html[index..]
+                        .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                        .map_or(html.len(), |i| index + i);
+                    value = decode_entities(&html[index..end]);
+                    end
+                }
+            };
+            index = value_end;
+        }
+        if !attributes.iter().any(|(key, _)| *key == name) {
+            attributes.push((name, value));
+        }
+    }
+}
+
+/// Finds the `</name` that ends a raw text element, ignoring case.
+fn find_end_tag(html: &str, start: usize, name: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    let mut index = start;
+    while let Some(offset) = html[index..].find("</") {
+        let tag = index + offset;
+        let name_end = tag + 2 + name.len();
+        if bytes.len() >= name_end
+            && bytes[tag + 2..name_end].eq_ignore_ascii_case(name.as_bytes())
+            && matches!(
+                bytes.get(name_end),
+                None | Some(b' ' | b'\t' | b'\n' | b'\r' | b'\x0c' | b'>' | b'/')
+            )
+        {
+            return Some(tag);
+        }
+        index = tag + 2;
+    }
+    None
+}
+
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("ensp", '\u{2002}'),
+    ("emsp", '\u{2003}'),
+    ("thinsp", '\u{2009}'),
+    ("zwnj", '\u{200c}'),
+    ("zwj", '\u{200d}'),
+    ("shy", '\u{ad}'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("hellip", '…'),
+    ("mdash", '—'),
+    ("ndash", '–'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("sbquo", '‚'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("bdquo", '„'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("lsaquo", '‹'),
+    ("rsaquo", '›'),
+    ("bull", '•'),
+    ("middot", '·'),
+    ("times", '×'),
+    ("divide", '÷'),
+    ("minus", '−'),
+    ("plusmn", '±'),
+    ("deg", '°'),
+    ("micro", 'µ'),
+    ("para", '¶'),
+    ("sect", '§'),
+    ("cent", '¢'),
+    ("pound", '£'),
+    ("yen", '¥'),
+    ("euro", '€'),
+    ("larr", '←'),
+    ("rarr", '→'),
+    ("uarr", '↑'),
+    ("darr", '↓'),
+    ("harr", '↔'),
+    ("iexcl", '¡'),
+    ("iquest", '¿'),
+];
+
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('&') {
+        decoded.push_str(&rest[..index]);
+        rest = &rest[index..];
+        match decode_entity(rest) {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decodes the character reference at the start of `text`, returning it and its length.
+fn decode_entity(text: &str) -> Option<(char, usize)> {
+    let body = &text[1..];
+    if let Some(number) = body.strip_prefix('#') {
+        let (digits, radix, prefix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (number, 10, 1),
+        };
+        let len = digits
+            .find(|c: char| !c.is_digit(radix))
+            .unwrap_or(digits.len());
+        if len == 0 {
+            return None;
+        }
+        let c = u32::from_str_radix(&digits[..len.min(8)], radix)
+            .ok()
+            .filter(|&code| code != 0)
+            .and_then(char::from_u32)
+            .unwrap_or('\u{fffd}');
+        let semicolon = usize::from(digits[len..].starts_with(';'));
+        return Some((c, 1 + prefix + len + semicolon));
+    }
+    let len = body.bytes().take(16).position(|c| c == b';')?;
+    let name = &body[..len];
+    NAMED_ENTITIES
+        .iter()
+        .find(|(entity, _)| *entity == name)
+        .map(|&(_, c)| (c, len + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serializes the tree without attributes, closing every element explicitly.
+    fn show(element: &Element) -> String {
+        let mut html = String::new();
+        for child in &element.children {
+            match child {
+                Node::Text(text) => html.push_str(text),
+                Node::Element(child) => {
+                    html.push_str(&format!("<{}>{}</{}>", child.name, show(child), child.name));
+                }
+            }
+        }
+        html
+    }
+
+    fn assert_parses(cases: &[(&str, &str)]) {
+        for (html, expected) in cases {
+            assert_eq!(show(&parse_html(html)), *expected, "{html}");
+        }
+    }
+
+    #[test]
+    fn implied_end_tags() {
+        assert_parses(&[
+            (
+                "<p>One<p>Two<div>Block</div>",
+                "<p>One</p><p>Two</p><div>Block</div>",
+            ),
+            (
+                "<ul><li>One<li>Two</ul>",
+                "<ul><li>One</li><li>Two</li></ul>",
+            ),
+            (
+                "<dl><dt>Term<dd>Description<dt>Next</dl>",
+                "<dl><dt>Term</dt><dd>Description</dd><dt>Next</dt></dl>",
+            ),
+            (
+                "<table><tr><td>A<td>B<tr><td>C</table>",
+                "<table><tr><td>A</td><td>B</td></tr><tr><td>C</td></tr></table>",
+            ),
+            (
+                "<table><thead><tr><th>H<tbody><tr><td>C</table>",
+                "<table><thead><tr><th>H</th></tr></thead><tbody><tr><td>C</td></tr></tbody></table>",
+            ),
+            (
+                "<head><title>T</title><body><p>Text",
+                "<head><title>T</title></head><body><p>Text</p></body>",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn malformed_tags() {
+        assert_parses(&[
+            (
+                "<b>bold <i>both</b> after</i>",
+                "<b>bold <i>both</i></b> after",
+            ),
+            ("<div><span>open", "<div><span>open</span></div>"),
+            ("</p>stray</div>end", "strayend"),
+            ("a < b <3 and <!-- comment --> c", "a < b <3 and  c"),
+            ("<p>unterminated <a href=\"x", "<p>unterminated <a></a></p>"),
+            (
+                "<img src=x alt='a > b'>text<br/>more",
+                "<img></img>text<br></br>more",
+            ),
+            (
+                "<script>if (a < b && c) {}</script><p>after",
+                "<script>if (a < b && c) {}</script><p>after</p>",
+            ),
+            (
+                "<td>A<table><td>nested</table>B",
+                "<td>A<table><td>nested</td></table>B</td>",
+            ),
+        ]);
+        let document = parse_html("<img src=x alt='a > b'>");
+        let image = document.elements().next().unwrap();
+        assert_eq!(image.attribute("src"), Some("x"));
+        assert_eq!(image.attribute("alt"), Some("a > b"));
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let html = format!("{}text{}", "<div>".repeat(10_000), "</div>".repeat(10_000));
+        let document = parse_html(&html);
+        let mut depth = 0;
+        let mut element = &document;
+        while let Some(child) = element.elements().last() {
+            depth += 1;
+            element = child;
+        }
+        assert_eq!(depth, MAX_DEPTH);
+        assert!(matches!(&element.children[..], [Node::Text(text)] if text == "text"));
+        assert_eq!(document.elements().count(), 1);
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            decode_entities("&amp; &lt;&gt; &quot;&#39; &#169; &#x41;&#X42; &nbsp;|"),
+            "& <> \"' © AB \u{a0}|"
+        );
+        assert_eq!(
+            decode_entities("&unknown; & &#; &#0; &#x110000;"),
+            "&unknown; & &#; \u{fffd} \u{fffd}"
+        );
+        let document = parse_html("<a title=\"x &amp; y\">&lt;b&gt; &copy;</a>");
+        let link = document.elements().next().unwrap();
+        assert_eq!(link.attribute("title"), Some("x & y"));
+        assert_eq!(show(&document), "<a><b> ©</a>");
+    }
+}
@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+pub const MAX_PAGE_SIZE: usize = 10 * 1024 * 1024;
+pub const MAX_IMAGE_SIZE: usize = 20 * 1024 * 1024;
+/// Bookmarks only need the metadata in the head of a page.
+pub const MAX_BOOKMARK_SIZE: usize = 256 * 1024;
+
+pub struct Download {
+    /// The URL after redirects.
+    pub url: String,
+    /// The media type of the `Content-Type` header, without parameters.
+    pub mime: Option<String>,
+    /// The `charset` parameter of the `Content-Type` header.
+    pub charset: Option<String>,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Request(reqwest::Error),
+    /// The response is larger than the limit.
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Request(e) => write!(f, "{e}"),
+            DownloadError::TooLarge(max_size) => {
+                write!(f, "response is larger than {max_size} bytes")
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(value: reqwest::Error) -> Self {
+        DownloadError::Request(value)
+    }
+}
+
+pub fn client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("Ruslin/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Downloads the URL, failing for error statuses and responses larger than `max_size` bytes.
+pub async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    max_size: usize,
+) -> Result<Download, DownloadError> {
+    fetch(client, url, max_size, false).await
+}
+
+/// Downloads the first `size` bytes of the URL, failing for error statuses.
+pub async fn download_head(
+    client: &reqwest::Client,
+    url: &str,
+    size: usize,
+) -> Result<Download, DownloadError> {
+    fetch(client, url, size, true).await
+}
+
+/// Downloads up to `max_size` bytes, cutting the response off there if `truncate` is set.
+async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    max_size: usize,
+    truncate: bool,
+) -> Result<Download, DownloadError> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    if !truncate
+        && response
+            .content_length()
+            .is_some_and(|length| length > max_size as u64)
+    {
+        return Err(DownloadError::TooLarge(max_size));
+    }
+    let url = response.url().to_string();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let mut parts = content_type.split(';');
+    let mime = parts
+        .next()
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .filter(|mime| !mime.is_empty());
+    let charset = parts
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string());
+    // The length is missing for chunked responses, and servers may send more than it says.
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > max_size {
+            if !truncate {
+                return Err(DownloadError::TooLarge(max_size));
+            }
+            bytes.extend_from_slice(&chunk[..max_size - bytes.len()]);
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Download {
+        url,
+        mime,
+        charset,
+        bytes,
+    })
+}
+
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/svg+xml", "svg"),
+    ("image/bmp", "bmp"),
+    ("image/avif", "avif"),
+    ("image/tiff", "tiff"),
+    ("image/x-icon", "ico"),
+    ("image/vnd.microsoft.icon", "ico"),
+];
+
+/// Returns the media type and file extension of a downloaded image. Servers often send a
+/// generic type, so the extension of the URL is tried next.
+pub fn image_type(download: &Download) -> Option<(&'static str, &'static str)> {
+    let by_mime = download.mime.as_deref().and_then(|mime| {
+        IMAGE_TYPES
+            .iter()
+            .find(|(image_mime, _)| *image_mime == mime)
+    });
+    let by_extension = || {
+        let extension = file_name(&download.url)?
+            .rsplit_once('.')?
+            .1
+            .to_ascii_lowercase();
+        let extension = if extension == "jpeg" {
+            "jpg"
+        } else {
+            &extension
+        };
+        IMAGE_TYPES
+            .iter()
+            .find(|(_, image_extension)| *image_extension == extension)
+    };
+    by_mime.or_else(by_extension).copied()
+}
+
+/// The last path segment of the URL.
+pub fn file_name(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let name = url.path_segments()?.next_back()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{serve, Response};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn get(url: &str, max_size: usize) -> Result<Download, DownloadError> {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(download(&client()?, url, max_size))
+    }
+
+    #[test]
+    fn success() {
+        let server = serve(vec![Response {
+            path: "/images/photo",
+            status: 200,
+            content_type: "Image/PNG; charset=binary",
+            body: PNG.to_vec(),
+            content_length: true,
+        }]);
+        let url = format!("{server}/images/photo");
+        let image = get(&url, MAX_IMAGE_SIZE).unwrap();
+        assert_eq!(image.url, url);
+        assert_eq!(image.mime.as_deref(), Some("image/png"));
+        assert_eq!(image.charset.as_deref(), Some("binary"));
+        assert_eq!(image.bytes, PNG);
+        assert_eq!(image_type(&image), Some(("image/png", "png")));
+        assert_eq!(file_name(&image.url).as_deref(), Some("photo"));
+    }
+
+    #[test]
+    fn error_status() {
+        let server = serve(vec![Response {
+            path: "/error",
+            status: 500,
+            content_type: "text/plain",
+            body: b"error".to_vec(),
+            content_length: true,
+        }]);
+        for path in ["/error", "/missing"] {
+            let error = get(&format!("{server}{path}"), MAX_PAGE_SIZE)
+                .err()
+                .unwrap();
+            assert!(
+                matches!(&error, DownloadError::Request(e) if e.is_status()),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn too_large() {
+        let response = |path, content_length| Response {
+            path,
+            status: 200,
+            content_type: "image/png",
+            body: vec![0; 100],
+            content_length,
+        };
+        let server = serve(vec![response("/length", true), response("/stream", false)]);
+        for path in ["/length", "/stream"] {
+            let url = format!("{server}{path}");
+            assert_eq!(get(&url, 100).unwrap().bytes.len(), 100);
+            let error = get(&url, 99).err().unwrap();
+            assert!(matches!(error, DownloadError::TooLarge(99)), "{error}");
+        }
+    }
+
+    #[test]
+    fn head() {
+        let response = |path, content_length| Response {
+            path,
+            status: 200,
+            content_type: "text/html",
+            body: (0..100).collect(),
+            content_length,
+        };
+        let server = serve(vec![response("/length", true), response("/stream", false)]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let client = client().unwrap();
+        for path in ["/length", "/stream"] {
+            let url = format!("{server}{path}");
+            let page = rt.block_on(download_head(&client, &url, 10)).unwrap();
+            assert_eq!(page.bytes, (0..10).collect::<Vec<u8>>());
+            let page = rt.block_on(download_head(&client, &url, 1000)).unwrap();
+            assert_eq!(page.bytes.len(), 100);
+        }
+    }
+
+    #[test]
+    fn image_type_from_extension() {
+        let download = |url: &str, mime: &str| Download {
+            url: url.to_string(),
+            mime: Some(mime.to_string()),
+            charset: None,
+            bytes: Vec::new(),
+        };
+        let image = download(
+            "https://example.com/a/b.JPEG?x=1",
+            "application/octet-stream",
+        );
+        assert_eq!(image_type(&image), Some(("image/jpeg", "jpg")));
+        let page = download("https://example.com/a/", "text/html");
+        assert_eq!(image_type(&page), None);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use ruslin_data::Note;
+
+use crate::pin;
+
+const ID_LENGTH: usize = 32;
+
+/// Returns the ids of the `:/id` links in the body, which link to notes or resources, in the
+/// order they first appear. HTML like `<img src=":/id">` is included.
+pub fn linked_ids(body: &str) -> Vec<&str> {
+    let mut ids: Vec<&str> = Vec::new();
+    for (start, _) in body.match_indices(":/") {
+        if let Some(id) = id_at(body, start + 2) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+    ids
+}
+
+fn id_at(body: &str, start: usize) -> Option<&str> {
+    let id = body.get(start..start + ID_LENGTH)?;
+    let is_id = id.bytes().all(|c| c.is_ascii_hexdigit())
+        && !body[start + ID_LENGTH..]
+            .bytes()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric());
+    is_id.then_some(id)
+}
+
+/// Replaces the ids of `:/id` links by the ones they map to.
+pub fn replace_linked_ids(body: &str, ids: &HashMap<String, String>) -> String {
+    let mut replaced = String::with_capacity(body.len());
+    let mut index = 0;
+    for (start, _) in body.match_indices(":/") {
+        let id_start = start + 2;
+        if id_start < index {
+            continue;
+        }
+        let Some(new_id) = id_at(body, id_start).and_then(|id| ids.get(id)) else {
+            continue;
+        };
+        replaced.push_str(&body[index..id_start]);
+        replaced.push_str(new_id);
+        index = id_start + ID_LENGTH;
+    }
+    replaced.push_str(&body[index..]);
+    replaced
+}
+
+/// A new note with the content and properties of `note`. Sync, sharing, conflict and pin
+/// state isn't copied.
+pub fn copy_note(note: &Note, parent_id: Option<String>, title: String, body: String) -> Note {
+    let mut copy = Note::new(parent_id, title, body);
+    copy.is_todo = note.is_todo;
+    copy.todo_due = note.todo_due;
+    copy.todo_completed = note.todo_completed;
+    copy.latitude = note.latitude;
+    copy.longitude = note.longitude;
+    copy.altitude = note.altitude;
+    copy.author = note.author.clone();
+    copy.source_url = note.source_url.clone();
+    copy.source = note.source.clone();
+    copy.source_application = note.source_application.clone();
+    // Application data that isn't a JSON object can't hold the pin state, and is kept as is.
+    copy.application_data = pin::set_pinned(&note.application_data, false)
+        .unwrap_or_else(|| note.application_data.clone());
+    copy.markup_language = note.markup_language;
+    copy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_note_unpinned() {
+        let mut note = Note::new(None, "Title".to_string(), "Body".to_string());
+        note.is_todo = true;
+        note.application_data = pin::set_pinned(r#"{"other.app":{"key":1}}"#, true).unwrap();
+        assert!(pin::is_pinned(&note.application_data));
+        let copy = copy_note(&note, None, "Copy".to_string(), note.body.clone());
+        assert_ne!(copy.id, note.id);
+        assert!(copy.is_todo);
+        assert!(!pin::is_pinned(&copy.application_data));
+        assert_eq!(copy.application_data, r#"{"other.app":{"key":1}}"#);
+
+        note.application_data = "not json".to_string();
+        let copy = copy_note(&note, None, "Copy".to_string(), note.body.clone());
+        assert_eq!(copy.application_data, "not json");
+    }
+}
@@ -0,0 +1,1182 @@
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, Event, LinkType, Options, Parser, Tag};
+
+use crate::link;
+use crate::math::MaskedText;
+use crate::utf16::{utf16_to_utf8_offset, utf8_to_utf16_offsets};
+
+/// Flips the task list marker (`[ ]`/`[x]`) that starts at the UTF-16 `offset`.
+/// Returns `None` if there is no marker at that offset.
+pub fn toggle_task_marker(text: &str, offset: usize) -> Option<String> {
+    let byte_offset = utf16_to_utf8_offset(text, offset)?;
+    let (range, is_checked) = Parser::new_ext(text, Options::all())
+        .into_offset_iter()
+        .find_map(|(event, range)| match event {
+            Event::TaskListMarker(is_checked) if range.start == byte_offset => {
+                Some((range, is_checked))
+            }
+            _ => None,
+        })?;
+    let mut toggled = String::with_capacity(text.len());
+    toggled.push_str(&text[..range.start]);
+    toggled.push_str(if is_checked { "[ ]" } else { "[x]" });
+    toggled.push_str(&text[range.end..]);
+    Some(toggled)
+}
+
+/// A formatting command of the editor toolbar.
+#[derive(Debug, Clone)]
+pub enum FormatCommand {
+    Bold,
+    Italic,
+    Strikethrough,
+    InlineCode,
+    /// Sets the heading level of the selected lines, `0` turns them into paragraphs.
+    Heading {
+        level: i32,
+    },
+    BulletList,
+    NumberedList,
+    TaskList,
+    Quote,
+    CodeBlock,
+    Link {
+        url: String,
+    },
+}
+
+/// The edited text, with the new selection in UTF-16 offsets.
+#[derive(Debug, Clone)]
+pub struct FFITextEdit {
+    pub text: String,
+    pub selection_start: i32,
+    pub selection_end: i32,
+}
+
+/// A replacement of `range` in the source. Edits of a command are sorted and don't overlap.
+struct Edit {
+    range: Range<usize>,
+    text: String,
+}
+
+impl Edit {
+    fn insert(offset: usize, text: impl Into<String>) -> Self {
+        Self {
+            range: offset..offset,
+            text: text.into(),
+        }
+    }
+
+    fn delete(range: Range<usize>) -> Self {
+        Self {
+            range,
+            text: String::new(),
+        }
+    }
+}
+
+/// Applies the formatting `command` to the UTF-16 selection of `text`. Toggling a format
+/// that is already applied to the selection removes its markup. Returns `None` if the
+/// selection is out of range.
+pub fn format_markdown(
+    text: &str,
+    selection_start: usize,
+    selection_end: usize,
+    command: &FormatCommand,
+) -> Option<FFITextEdit> {
+    let start = utf16_to_utf8_offset(text, selection_start.min(selection_end))?;
+    let end = utf16_to_utf8_offset(text, selection_start.max(selection_end))?;
+    let selection = start..end;
+    let (edits, new_selection) = match command {
+        FormatCommand::Bold => toggle_inline(text, selection.clone(), InlineKind::Strong),
+        FormatCommand::Italic => toggle_inline(text, selection.clone(), InlineKind::Emphasis),
+        FormatCommand::Strikethrough => {
+            toggle_inline(text, selection.clone(), InlineKind::Strikethrough)
+        }
+        FormatCommand::InlineCode => toggle_inline(text, selection.clone(), InlineKind::Code),
+        FormatCommand::Heading { level } => (set_heading(text, selection.clone(), *level), None),
+        FormatCommand::BulletList => (toggle_list(text, selection.clone(), ListKind::Bullet), None),
+        FormatCommand::NumberedList => (
+            toggle_list(text, selection.clone(), ListKind::Numbered),
+            None,
+        ),
+        FormatCommand::TaskList => (toggle_list(text, selection.clone(), ListKind::Task), None),
+        FormatCommand::Quote => (toggle_quote(text, selection.clone()), None),
+        FormatCommand::CodeBlock => toggle_code_block(text, selection.clone()),
+        FormatCommand::Link { url } => toggle_link(text, selection.clone(), url),
+    };
+    Some(apply_edits(text, &edits, selection, new_selection))
+}
+
+/// Applies the edits and maps the selection through them. The selection start moves past
+/// text inserted at it, the selection end stays in front of it. `new_selection`, in byte
+/// offsets of the edited text, replaces the selection if set.
+fn apply_edits(
+    text: &str,
+    edits: &[Edit],
+    selection: Range<usize>,
+    new_selection: Option<Range<usize>>,
+) -> FFITextEdit {
+    let mut edited = String::with_capacity(text.len() + 16);
+    let mut offset = 0;
+    for edit in edits {
+        edited.push_str(&text[offset..edit.range.start]);
+        edited.push_str(&edit.text);
+        offset = edit.range.end;
+    }
+    edited.push_str(&text[offset..]);
+
+    let (start, end) = match new_selection {
+        Some(selection) => (selection.start, selection.end),
+        None => {
+            let start = map_offset(edits, selection.start, true);
+            let end = map_offset(edits, selection.end, selection.is_empty());
+            (start, end.max(start))
+        }
+    };
+    let utf16_offsets = utf8_to_utf16_offsets(&edited);
+    FFITextEdit {
+        selection_start: utf16_offsets[start] as i32,
+        selection_end: utf16_offsets[end] as i32,
+        text: edited,
+    }
+}
+
+fn map_offset(edits: &[Edit], offset: usize, after_insert: bool) -> usize {
+    let mut mapped = offset;
+    for edit in edits {
+        let inserted = edit.text.len();
+        if offset > edit.range.end || (offset == edit.range.end && after_insert) {
+            mapped = mapped + inserted - edit.range.len();
+        } else if offset > edit.range.start {
+            mapped =
+                mapped - (offset - edit.range.start) + (offset - edit.range.start).min(inserted);
+        }
+    }
+    mapped
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InlineKind {
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Code,
+}
+
+/// Removes the innermost markup of `kind` that contains the selection, or wraps the
+/// selection in it.
+fn toggle_inline(
+    text: &str,
+    selection: Range<usize>,
+    kind: InlineKind,
+) -> (Vec<Edit>, Option<Range<usize>>) {
+    let masked = MaskedText::new(text);
+    let mut found: Option<Range<usize>> = None;
+    for (event, range) in Parser::new_ext(&masked.text, Options::all()).into_offset_iter() {
+        let event_kind = match event {
+            Event::Start(Tag::Emphasis) => InlineKind::Emphasis,
+            Event::Start(Tag::Strong) => InlineKind::Strong,
+            Event::Start(Tag::Strikethrough) => InlineKind::Strikethrough,
+            Event::Code(_) if masked.span(&range).is_none() => InlineKind::Code,
+            _ => continue,
+        };
+        if event_kind == kind && range.start <= selection.start && selection.end <= range.end {
+            found = Some(range);
+        }
+    }
+
+    if let Some(mut range) = found {
+        let mut delimiter = match kind {
+            InlineKind::Emphasis => 1,
+            InlineKind::Strong => 2,
+            InlineKind::Strikethrough => count_leading(&text[range.clone()], b'~'),
+            InlineKind::Code => count_leading(&text[range.clone()], b'`'),
+        };
+        if kind == InlineKind::Code {
+            // The range of a code span at the end of a heading includes the whitespace after it.
+            range.end = range.start + text[range.clone()].trim_end().len();
+            // Removes the space that keeps backticks at the ends apart, too.
+            let content = &text[range.start + delimiter..range.end - delimiter];
+            if content.starts_with(' ') && content.ends_with(' ') && !content.trim().is_empty() {
+                delimiter += 1;
+            }
+        }
+        let edits = vec![
+            Edit::delete(range.start..range.start + delimiter),
+            Edit::delete(range.end - delimiter..range.end),
+        ];
+        return (edits, None);
+    }
+
+    // Markup around whitespace isn't recognized, e.g. `** a**`.
+    let selected = &text[selection.clone()];
+    let start = selection.start + (selected.len() - selected.trim_start().len());
+    let end = (selection.end - (selected.len() - selected.trim_end().len())).max(start);
+    let delimiter = match kind {
+        InlineKind::Emphasis => "*".to_string(),
+        InlineKind::Strong => "**".to_string(),
+        InlineKind::Strikethrough => "~~".to_string(),
+        InlineKind::Code => "`".repeat(longest_run(&text[start..end], b'`') + 1),
+    };
+    if start == end {
+        let cursor = end + delimiter.len();
+        return (
+            vec![Edit::insert(end, delimiter.repeat(2))],
+            Some(cursor..cursor),
+        );
+    }
+    // Code spans strip one space on both sides, which keeps backticks at the ends apart.
+    let (open, close) = if kind == InlineKind::Code
+        && (text[start..end].starts_with('`') || text[start..end].ends_with('`'))
+    {
+        (format!("{delimiter} "), format!(" {delimiter}"))
+    } else {
+        (delimiter.clone(), delimiter)
+    };
+    // Selects the text without the whitespace around it.
+    let new_selection = start + open.len()..end + open.len();
+    let edits = vec![Edit::insert(start, open), Edit::insert(end, close)];
+    (edits, Some(new_selection))
+}
+
+fn count_leading(text: &str, c: u8) -> usize {
+    text.bytes().take_while(|&b| b == c).count()
+}
+
+fn longest_run(text: &str, c: u8) -> usize {
+    text.bytes()
+        .fold((0, 0), |(longest, run), b| {
+            let run = if b == c { run + 1 } else { 0 };
+            (longest.max(run), run)
+        })
+        .0
+}
+
+/// Returns the ranges of the lines touched by the selection, without line breaks. A
+/// selection ending at the start of a line doesn't touch that line.
+fn selected_lines(text: &str, selection: Range<usize>) -> Vec<Range<usize>> {
+    let mut end = selection.end;
+    if end > selection.start && text[..end].ends_with('\n') {
+        end -= 1;
+    }
+    let start = text[..selection.start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let end = text[end..]
+        .find('\n')
+        .map_or(text.len(), |index| end + index);
+    let mut lines = Vec::new();
+    let mut line_start = start;
+    for line in text[start..end].split('\n') {
+        lines.push(line_start..line_start + line.len());
+        line_start += line.len() + 1;
+    }
+    lines
+}
+
+/// Returns the number of leading spaces and tabs of a line.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// Returns the range of the `#` marker of an ATX heading with the following spaces, and the
+/// level of the heading.
+fn heading_prefix(text: &str, line: &Range<usize>) -> Option<(Range<usize>, usize)> {
+    let content = &text[line.clone()];
+    let indent = indentation(content);
+    if indent > 3 {
+        return None;
+    }
+    let level = count_leading(&content[indent..], b'#');
+    let rest = &content[indent + level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let end = indent + level + indentation(rest);
+    Some((line.start + indent..line.start + end, level))
+}
+
+fn set_heading(text: &str, selection: Range<usize>, level: i32) -> Vec<Edit> {
+    let lines = selected_lines(text, selection);
+    let level = level.clamp(0, 6) as usize;
+    // Setting the level the lines already have toggles it off.
+    let toggle_off = lines.iter().all(|line| {
+        text[line.clone()].trim().is_empty()
+            || heading_prefix(text, line).is_some_and(|(_, current)| current == level)
+    });
+    lines
+        .iter()
+        .filter(|line| !text[(*line).clone()].trim().is_empty())
+        .filter_map(|line| {
+            let prefix = if level == 0 || toggle_off {
+                String::new()
+            } else {
+                format!("{} ", "#".repeat(level))
+            };
+            match heading_prefix(text, line) {
+                Some((range, _)) => Some(Edit {
+                    range,
+                    text: prefix,
+                }),
+                None if !prefix.is_empty() => Some(Edit::insert(
+                    line.start + indentation(&text[line.clone()]),
+                    prefix,
+                )),
+                None => None,
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ListKind {
+    Bullet,
+    Numbered,
+    Task,
+}
+
+/// A list item marker, like `- `, `1. ` or `- [ ] `.
+#[derive(Debug)]
+struct ListMarker {
+    /// The marker with its following spaces, without the indentation.
+    range: Range<usize>,
+    kind: ListKind,
+    /// The bullet character, or the delimiter (`.` or `)`) of an ordered list.
+    delimiter: char,
+    number: u64,
+}
+
+/// Parses the list item marker at the start of a line.
+fn list_marker(text: &str, line: &Range<usize>) -> Option<ListMarker> {
+    let content = &text[line.clone()];
+    let start = indentation(content);
+    let rest = &content[start..];
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let (kind, delimiter, number, marker_len) = match rest.as_bytes().first()? {
+        c @ (b'-' | b'*' | b'+') => (ListKind::Bullet, *c as char, 0, 1),
+        _ if (1..=9).contains(&digits) => match rest.as_bytes().get(digits) {
+            Some(c @ (b'.' | b')')) => (
+                ListKind::Numbered,
+                *c as char,
+                rest[..digits].parse().ok()?,
+                digits + 1,
+            ),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let after = &rest[marker_len..];
+    if !(after.is_empty() || after.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let mut end = marker_len + indentation(after);
+    let mut kind = kind;
+    let task = &rest[end..];
+    if kind == ListKind::Bullet
+        && (task.starts_with("[ ]") || task.starts_with("[x]") || task.starts_with("[X]"))
+        && (task.len() == 3 || task[3..].starts_with([' ', '\t']))
+    {
+        kind = ListKind::Task;
+        end += 3 + indentation(&task[3..]);
+    }
+    Some(ListMarker {
+        range: line.start + start..line.start + start + end,
+        kind,
+        delimiter,
+        number,
+    })
+}
+
+fn toggle_list(text: &str, selection: Range<usize>, kind: ListKind) -> Vec<Edit> {
+    let lines: Vec<Range<usize>> = selected_lines(text, selection)
+        .into_iter()
+        .filter(|line| !text[line.clone()].trim().is_empty())
+        .collect();
+    let toggle_off = !lines.is_empty()
+        && lines
+            .iter()
+            .all(|line| list_marker(text, line).is_some_and(|marker| marker.kind == kind));
+    let mut number = 0;
+    lines
+        .iter()
+        .map(|line| {
+            let marker = list_marker(text, line);
+            number += 1;
+            let prefix = match kind {
+                _ if toggle_off => String::new(),
+                ListKind::Bullet => "- ".to_string(),
+                ListKind::Numbered => format!("{number}. "),
+                ListKind::Task => "- [ ] ".to_string(),
+            };
+            match marker {
+                Some(marker) => Edit {
+                    range: marker.range,
+                    text: prefix,
+                },
+                None => Edit::insert(line.start + indentation(&text[line.clone()]), prefix),
+            }
+        })
+        .collect()
+}
+
+/// Returns the range of the `>` marker with its following space.
+fn quote_prefix(text: &str, line: &Range<usize>) -> Option<Range<usize>> {
+    let content = &text[line.clone()];
+    let indent = indentation(content);
+    if indent > 3 || !content[indent..].starts_with('>') {
+        return None;
+    }
+    let end = if content[indent + 1..].starts_with(' ') {
+        indent + 2
+    } else {
+        indent + 1
+    };
+    Some(line.start + indent..line.start + end)
+}
+
+fn toggle_quote(text: &str, selection: Range<usize>) -> Vec<Edit> {
+    let lines = selected_lines(text, selection);
+    let toggle_off = lines.iter().all(|line| quote_prefix(text, line).is_some());
+    lines
+        .iter()
+        .map(|line| match quote_prefix(text, line) {
+            Some(range) if toggle_off => Edit::delete(range),
+            _ if text[line.clone()].is_empty() => Edit::insert(line.start, ">"),
+            _ => Edit::insert(line.start, "> "),
+        })
+        .collect()
+}
+
+/// Removes the fences of the code block containing the selection, or fences the selected
+/// lines.
+fn toggle_code_block(text: &str, selection: Range<usize>) -> (Vec<Edit>, Option<Range<usize>>) {
+    let code_block = Parser::new_ext(text, Options::all())
+        .into_offset_iter()
+        .find_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_)))
+                if range.start <= selection.start && selection.end <= range.end =>
+            {
+                Some(range)
+            }
+            _ => None,
+        });
+    if let Some(range) = code_block {
+        let block = &text[range.clone()];
+        let Some(opening_end) = block.find('\n') else {
+            return (vec![Edit::delete(range)], None);
+        };
+        let mut edits = vec![Edit::delete(range.start..range.start + opening_end + 1)];
+        let body = block.trim_end_matches('\n');
+        let closing_start = body.rfind('\n').map_or(0, |index| index + 1);
+        let closing = body[closing_start..].trim();
+        if closing_start > opening_end && (closing.starts_with("```") || closing.starts_with("~~~"))
+        {
+            let mut closing = range.start + closing_start..range.start + body.len();
+            // Removes the line break after the fence, or before it at the end of the text.
+            if text[closing.end..].starts_with('\n') {
+                closing.end += 1;
+            } else if closing_start > opening_end + 1 {
+                closing.start -= 1;
+            }
+            edits.push(Edit::delete(closing));
+        }
+        return (edits, None);
+    }
+
+    let lines = selected_lines(text, selection);
+    let start = lines.first().map_or(0, |line| line.start);
+    let end = lines.last().map_or(text.len(), |line| line.end);
+    let fence = "`".repeat(longest_run(&text[start..end], b'`').max(2) + 1);
+    if start == end {
+        let cursor = start + fence.len() + 1;
+        let edits = vec![Edit::insert(start, format!("{fence}\n\n{fence}"))];
+        return (edits, Some(cursor..cursor));
+    }
+    let edits = vec![
+        Edit::insert(start, format!("{fence}\n")),
+        Edit::insert(end, format!("\n{fence}")),
+    ];
+    (edits, None)
+}
+
+/// Removes the link containing the selection and keeps its text, or links the selection.
+fn toggle_link(
+    text: &str,
+    selection: Range<usize>,
+    url: &str,
+) -> (Vec<Edit>, Option<Range<usize>>) {
+    let link = Parser::new_ext(text, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Link(link_type, _, _))
+                if range.start <= selection.start && selection.end <= range.end =>
+            {
+                Some((link_type, range))
+            }
+            _ => None,
+        })
+        .last();
+    if let Some((link_type, range)) = link {
+        let edits = match link_type {
+            LinkType::Autolink | LinkType::Email => vec![
+                Edit::delete(range.start..range.start + 1),
+                Edit::delete(range.end - 1..range.end),
+            ],
+            _ => {
+                let Some(text_end) = link::find_text_end(text.as_bytes(), range.start + 1) else {
+                    return (Vec::new(), None);
+                };
+                let mut end = range.end;
+                // The range of a collapsed link doesn't include the trailing `[]`.
+                if matches!(link_type, LinkType::Collapsed | LinkType::CollapsedUnknown)
+                    && text[end..].starts_with("[]")
+                {
+                    end += 2;
+                }
+                vec![
+                    Edit::delete(range.start..range.start + 1),
+                    Edit::delete(text_end..end),
+                ]
+            }
+        };
+        return (edits, None);
+    }
+
+    let url = if url.contains([' ', '(', ')']) {
+        format!("<{url}>")
+    } else {
+        url.to_string()
+    };
+    if selection.is_empty() {
+        let cursor = selection.start + 1;
+        let edits = vec![Edit::insert(selection.start, format!("[]({url})"))];
+        return (edits, Some(cursor..cursor));
+    }
+    let edits = vec![
+        Edit::insert(selection.start, "["),
+        Edit::insert(selection.end, format!("]({url})")),
+    ];
+    (edits, None)
+}
+
+/// A list item, with the nesting computed like `parse_markdown` does.
+struct ListItem {
+    range: Range<usize>,
+    /// Index of the list of the item, in document order.
+    list: usize,
+    nested_level: usize,
+}
+
+/// The list items and block quotes of a text.
+struct Blocks {
+    items: Vec<ListItem>,
+    quotes: Vec<Range<usize>>,
+}
+
+impl Blocks {
+    fn new(text: &str) -> Self {
+        let mut items = Vec::new();
+        let mut quotes = Vec::new();
+        let mut lists: Vec<usize> = Vec::new();
+        let mut list_count = 0;
+        for (event, range) in Parser::new_ext(text, Options::all()).into_offset_iter() {
+            match event {
+                Event::Start(Tag::List(_)) => {
+                    lists.push(list_count);
+                    list_count += 1;
+                }
+                Event::End(Tag::List(_)) => {
+                    lists.pop();
+                }
+                Event::Start(Tag::Item) => items.push(ListItem {
+                    range,
+                    list: lists.last().copied().unwrap_or_default(),
+                    nested_level: lists.len(),
+                }),
+                Event::Start(Tag::BlockQuote) => quotes.push(range),
+                _ => {}
+            }
+        }
+        Self { items, quotes }
+    }
+
+    /// Returns the item of the list item marker at `marker_start`. The item may start at the
+    /// indentation in front of its marker, from `line_start` on.
+    fn item(&self, line_start: usize, marker_start: usize) -> Option<&ListItem> {
+        self.items
+            .iter()
+            .find(|item| (line_start..=marker_start).contains(&item.range.start))
+    }
+}
+
+/// The container markers at the start of a line.
+struct LinePrefix {
+    /// The end of the `>` markers of block quotes.
+    quote_end: usize,
+    marker: Option<ListMarker>,
+}
+
+/// Parses the quote and list item markers of a line. Markers that aren't part of a block
+/// quote or list item, like in code blocks, are ignored.
+fn line_prefix(text: &str, line: &Range<usize>, blocks: &Blocks) -> LinePrefix {
+    let mut quote_end = line.start;
+    if blocks
+        .quotes
+        .iter()
+        .any(|quote| quote.start <= line.start && line.start < quote.end)
+    {
+        while let Some(range) = quote_prefix(text, &(quote_end..line.end)) {
+            quote_end = range.end;
+        }
+    }
+    let marker = list_marker(text, &(quote_end..line.end))
+        .filter(|marker| blocks.item(quote_end, marker.range.start).is_some());
+    LinePrefix { quote_end, marker }
+}
+
+fn line_at(text: &str, offset: usize) -> Range<usize> {
+    let start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let end = text[offset..]
+        .find('\n')
+        .map_or(text.len(), |index| offset + index);
+    start..end
+}
+
+/// Handles Enter at the UTF-16 `cursor` in a list item or block quote: continues the list
+/// item marker and the quote markers on the new line, and renumbers the following items of
+/// an ordered list. Enter on an empty item or quote line removes its marker instead.
+/// Returns `None` if the cursor isn't in a list item or quote, for a plain line break.
+pub fn handle_newline(text: &str, cursor: usize) -> Option<FFITextEdit> {
+    let cursor = utf16_to_utf8_offset(text, cursor)?;
+    let blocks = Blocks::new(text);
+    let line = line_at(text, cursor);
+    let prefix = line_prefix(text, &line, &blocks);
+    let content_start = prefix
+        .marker
+        .as_ref()
+        .map_or(prefix.quote_end, |marker| marker.range.end);
+    if content_start == line.start || cursor < content_start {
+        return None;
+    }
+
+    if text[content_start..line.end].trim().is_empty() {
+        let range = match prefix.marker {
+            Some(_) => prefix.quote_end..line.end,
+            None => line.start..line.end,
+        };
+        let cursor = range.start;
+        let edits = vec![Edit::delete(range)];
+        return Some(apply_edits(
+            text,
+            &edits,
+            cursor..cursor,
+            Some(cursor..cursor),
+        ));
+    }
+
+    let mut continuation = format!("\n{}", &text[line.start..prefix.quote_end]);
+    let mut renumber_edits = Vec::new();
+    if let Some(marker) = &prefix.marker {
+        continuation.push_str(&text[prefix.quote_end..marker.range.start]);
+        match marker.kind {
+            ListKind::Bullet => continuation.push_str(&format!("{} ", marker.delimiter)),
+            ListKind::Task => continuation.push_str(&format!("{} [ ] ", marker.delimiter)),
+            ListKind::Numbered => {
+                continuation.push_str(&format!("{}{} ", marker.number + 1, marker.delimiter));
+                renumber_edits = renumber_following(text, &blocks, marker, marker.number + 2);
+            }
+        }
+    }
+    let new_cursor = cursor + continuation.len();
+    let mut edits = vec![Edit::insert(cursor, continuation)];
+    edits.extend(renumber_edits);
+    Some(apply_edits(
+        text,
+        &edits,
+        cursor..cursor,
+        Some(new_cursor..new_cursor),
+    ))
+}
+
+/// Renumbers the items after the item of `marker` in its ordered list, starting at `number`.
+fn renumber_following(
+    text: &str,
+    blocks: &Blocks,
+    marker: &ListMarker,
+    mut number: u64,
+) -> Vec<Edit> {
+    let line_start = line_at(text, marker.range.start).start;
+    let Some(item) = blocks.item(line_start, marker.range.start) else {
+        return Vec::new();
+    };
+    blocks
+        .items
+        .iter()
+        .filter(|sibling| sibling.list == item.list && sibling.range.start > item.range.start)
+        .filter_map(|sibling| {
+            let line = line_at(text, sibling.range.start);
+            let sibling_marker = line_prefix(text, &line, blocks).marker?;
+            let edit = renumber(text, &sibling_marker, number);
+            number += 1;
+            edit
+        })
+        .collect()
+}
+
+/// Replaces the number of an ordered list item marker, if it differs.
+fn renumber(text: &str, marker: &ListMarker, number: u64) -> Option<Edit> {
+    if marker.kind != ListKind::Numbered || marker.number == number {
+        return None;
+    }
+    let start = marker.range.start;
+    Some(Edit {
+        range: start..start + count_digits(&text[marker.range.clone()]),
+        text: number.to_string(),
+    })
+}
+
+fn count_digits(text: &str) -> usize {
+    text.bytes().take_while(u8::is_ascii_digit).count()
+}
+
+/// Indents the list items touched by the UTF-16 selection, nesting them into the previous
+/// item, or outdents them to the level of their parent item. Nested items and continuation
+/// lines move with their item. Returns `None` if no list item can be moved.
+pub fn indent(
+    text: &str,
+    selection_start: usize,
+    selection_end: usize,
+    outdent: bool,
+) -> Option<FFITextEdit> {
+    let start = utf16_to_utf8_offset(text, selection_start.min(selection_end))?;
+    let end = utf16_to_utf8_offset(text, selection_start.max(selection_end))?;
+    let blocks = Blocks::new(text);
+
+    let mut moved: Vec<(&ListItem, isize)> = Vec::new();
+    for line in selected_lines(text, start..end) {
+        let prefix = line_prefix(text, &line, &blocks);
+        let Some(marker) = prefix.marker else {
+            continue;
+        };
+        let item = blocks.item(prefix.quote_end, marker.range.start)?;
+        // Nested items already move with their parent.
+        if moved.iter().any(|(parent, _)| {
+            parent.range.start < item.range.start && item.range.end <= parent.range.end
+        }) {
+            continue;
+        }
+        let column = marker.range.start - prefix.quote_end;
+        let new_column = if outdent {
+            // The parent is the innermost item one level up that contains the item.
+            blocks
+                .items
+                .iter()
+                .rev()
+                .find(|parent| {
+                    parent.nested_level + 1 == item.nested_level
+                        && parent.range.start < item.range.start
+                        && item.range.end <= parent.range.end
+                })
+                .map(|parent| item_column(text, &blocks, parent.range.start).0)
+        } else {
+            // The item becomes a child of the previous item of its list.
+            blocks
+                .items
+                .iter()
+                .rev()
+                .find(|sibling| sibling.list == item.list && sibling.range.start < item.range.start)
+                .map(|sibling| item_column(text, &blocks, sibling.range.start).1)
+        };
+        match new_column {
+            Some(new_column) if new_column != column => {
+                moved.push((item, new_column as isize - column as isize))
+            }
+            _ => {}
+        }
+    }
+    if moved.is_empty() {
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    // The first lines of the ordered items whose lists are renumbered afterwards.
+    let mut renumbered = Vec::new();
+    for &(item, delta) in &moved {
+        for line in selected_lines(text, item.range.clone()) {
+            let prefix = line_prefix(text, &line, &blocks);
+            let content = &text[prefix.quote_end..line.end];
+            // Blank lines stay blank.
+            if content.trim().is_empty() {
+                continue;
+            }
+            if delta > 0 {
+                edits.push(Edit::insert(prefix.quote_end, " ".repeat(delta as usize)));
+            } else {
+                let removed = indentation(content).min(delta.unsigned_abs());
+                edits.push(Edit::delete(prefix.quote_end..prefix.quote_end + removed));
+            }
+        }
+        // Only an ordered item numbered `1` starts a list after a paragraph, so the item and
+        // the one taking its place in the old list start at `1` until their lists are
+        // renumbered below.
+        let following = blocks.items.iter().find(|sibling| {
+            sibling.list == item.list
+                && sibling.range.start >= item.range.end
+                && !moved
+                    .iter()
+                    .any(|(moved, _)| moved.range.start == sibling.range.start)
+        });
+        for item in std::iter::once(item).chain(following) {
+            let line = line_at(text, item.range.start);
+            let Some(marker) = line_prefix(text, &line, &blocks).marker else {
+                continue;
+            };
+            if marker.kind != ListKind::Numbered {
+                continue;
+            }
+            if let Some(edit) = renumber(text, &marker, 1) {
+                if !edits.iter().any(|other| other.range == edit.range) {
+                    edits.push(edit);
+                }
+            }
+            renumbered.push(line.start);
+        }
+    }
+    edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+    let indented = apply_edits(text, &edits, start..end, None);
+    if renumbered.is_empty() {
+        return Some(indented);
+    }
+
+    // Numbers the items from the previous item of their new list on, and the items after
+    // them.
+    let text = &indented.text;
+    let blocks = Blocks::new(text);
+    let mut first_items: Vec<(&ListItem, ListMarker)> = Vec::new();
+    for line_start in renumbered {
+        let line = line_at(text, map_offset(&edits, line_start, false));
+        let prefix = line_prefix(text, &line, &blocks);
+        let Some(marker) = prefix.marker else {
+            continue;
+        };
+        let Some(item) = blocks.item(prefix.quote_end, marker.range.start) else {
+            continue;
+        };
+        match first_items
+            .iter_mut()
+            .find(|(first, _)| first.list == item.list)
+        {
+            Some(first) if first.0.range.start > item.range.start => *first = (item, marker),
+            Some(_) => {}
+            None => first_items.push((item, marker)),
+        }
+    }
+    let mut edits = Vec::new();
+    for (item, marker) in first_items {
+        let number = blocks
+            .items
+            .iter()
+            .rev()
+            .find(|sibling| sibling.list == item.list && sibling.range.start < item.range.start)
+            .and_then(|previous| {
+                let line = line_at(text, previous.range.start);
+                line_prefix(text, &line, &blocks).marker
+            })
+            .map_or(marker.number, |previous| previous.number + 1);
+        edits.extend(renumber(text, &marker, number));
+        edits.extend(renumber_following(text, &blocks, &marker, number + 1));
+    }
+    edits.sort_by_key(|edit| edit.range.start);
+    let start = utf16_to_utf8_offset(text, indented.selection_start as usize)?;
+    let end = utf16_to_utf8_offset(text, indented.selection_end as usize)?;
+    Some(apply_edits(text, &edits, start..end, None))
+}
+
+/// Returns the column of the marker of the item starting at `start`, and the column of its
+/// content, after the quote markers of the line.
+fn item_column(text: &str, blocks: &Blocks, start: usize) -> (usize, usize) {
+    let line = line_at(text, start);
+    let prefix = line_prefix(text, &line, blocks);
+    match prefix.marker {
+        Some(marker) => (
+            marker.range.start - prefix.quote_end,
+            marker.range.end - prefix.quote_end,
+        ),
+        None => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits the `«` and `»` selection markers off `text`, returning UTF-16 offsets.
+    fn split_selection(text: &str) -> (String, usize, usize) {
+        let start = text.find('«').unwrap();
+        let end = text.find('»').unwrap() - '«'.len_utf8();
+        let text = text.replace(['«', '»'], "");
+        let utf16_offsets = utf8_to_utf16_offsets(&text);
+        (text, utf16_offsets[start], utf16_offsets[end])
+    }
+
+    fn join_selection(edit: FFITextEdit) -> String {
+        let offset = |utf16: i32| {
+            let utf16_offsets = utf8_to_utf16_offsets(&edit.text);
+            utf16_offsets
+                .iter()
+                .position(|&o| o == utf16 as usize)
+                .unwrap()
+        };
+        let (start, end) = (offset(edit.selection_start), offset(edit.selection_end));
+        format!(
+            "{}«{}»{}",
+            &edit.text[..start],
+            &edit.text[start..end],
+            &edit.text[end..]
+        )
+    }
+
+    fn format(text: &str, command: &FormatCommand) -> String {
+        let (text, start, end) = split_selection(text);
+        join_selection(format_markdown(&text, start, end, command).unwrap())
+    }
+
+    fn check(cases: &[(FormatCommand, &str, &str)]) {
+        for (command, text, expected) in cases {
+            assert_eq!(&format(text, command), expected, "{command:?} on {text:?}");
+        }
+    }
+
+    #[test]
+    fn bold_italic_strikethrough() {
+        use FormatCommand::*;
+        check(&[
+            (Bold, "a «b» c", "a **«b»** c"),
+            (Bold, "a **«b»** c", "a «b» c"),
+            (Bold, "**a«»b**", "a«»b"),
+            (Bold, "a «»b", "a **«»**b"),
+            (Bold, "a« b »c", "a **«b»** c"),
+            (Bold, "«a\nb»", "**«a\nb»**"),
+            (Bold, "😀 «😀» x", "😀 **«😀»** x"),
+            (Bold, "😀 **«😀»** x", "😀 «😀» x"),
+            (Italic, "«a»", "*«a»*"),
+            (Italic, "*«a»*", "«a»"),
+            (Italic, "**«a»**", "***«a»***"),
+            (Italic, "«»", "*«»*"),
+            (Italic, "«a\nb»", "*«a\nb»*"),
+            (Italic, "😀«😀»", "😀*«😀»*"),
+            (Strikethrough, "«a»", "~~«a»~~"),
+            (Strikethrough, "~«a»~", "«a»"),
+            (Strikethrough, "~~«a»~~", "«a»"),
+            (Strikethrough, "«»", "~~«»~~"),
+            (Strikethrough, "«a\nb»", "~~«a\nb»~~"),
+            (Strikethrough, "~~😀«😀»~~", "😀«😀»"),
+        ]);
+    }
+
+    #[test]
+    fn inline_code() {
+        use FormatCommand::*;
+        check(&[
+            (InlineCode, "a «b» c", "a `«b»` c"),
+            (InlineCode, "a `«b»` c", "a «b» c"),
+            (InlineCode, "`a«»b`", "a«»b"),
+            (InlineCode, "«a`b»", "``«a`b»``"),
+            (InlineCode, "``«a`b»``", "«a`b»"),
+            (InlineCode, "«`a»", "`` «`a» ``"),
+            (InlineCode, "`` «`a» ``", "«`a»"),
+            (InlineCode, "«a`»", "`` «a`» ``"),
+            (InlineCode, "`` «a`» ``", "«a`»"),
+            (InlineCode, "`« »`", "« »"),
+            (InlineCode, "# `«a»`  ", "# «a»  "),
+            (InlineCode, "«»", "`«»`"),
+            (InlineCode, "«a\nb»", "`«a\nb»`"),
+            (InlineCode, "😀 «😀»", "😀 `«😀»`"),
+            (InlineCode, "😀 `` «😀`» ``", "😀 «😀`»"),
+            (InlineCode, "«$a$»", "`«$a$»`"),
+        ]);
+    }
+
+    #[test]
+    fn headings() {
+        use FormatCommand::*;
+        check(&[
+            (Heading { level: 2 }, "«a»", "## «a»"),
+            (Heading { level: 2 }, "## «a»", "«a»"),
+            (Heading { level: 2 }, "# «a»", "## «a»"),
+            (Heading { level: 0 }, "### «a»", "«a»"),
+            (Heading { level: 1 }, "a«»b", "# a«»b"),
+            (Heading { level: 1 }, "«a\nb»", "# «a\n# b»"),
+            (Heading { level: 1 }, "# «a\nb»", "# «a\n# b»"),
+            (Heading { level: 1 }, "# «a\n# b»", "«a\nb»"),
+            (Heading { level: 3 }, "😀 «😀»", "### 😀 «😀»"),
+        ]);
+    }
+
+    #[test]
+    fn lists() {
+        use FormatCommand::*;
+        check(&[
+            (BulletList, "«a»", "- «a»"),
+            (BulletList, "- «a»", "«a»"),
+            (BulletList, "a«»", "- a«»"),
+            (BulletList, "«a\nb»", "- «a\n- b»"),
+            (BulletList, "- «a\n- b»", "«a\nb»"),
+            (BulletList, "😀\n«😀»", "😀\n- «😀»"),
+            (NumberedList, "«a»", "1. «a»"),
+            (NumberedList, "1. «a»", "«a»"),
+            (NumberedList, "«»a", "1. «»a"),
+            (NumberedList, "«a\nb»", "1. «a\n2. b»"),
+            (NumberedList, "1. «a\n2. b»", "«a\nb»"),
+            (NumberedList, "😀 «😀»", "1. 😀 «😀»"),
+            (TaskList, "«a»", "- [ ] «a»"),
+            (TaskList, "- [x] «a»", "«a»"),
+            (TaskList, "a«»", "- [ ] a«»"),
+            (TaskList, "«a\nb»", "- [ ] «a\n- [ ] b»"),
+            (TaskList, "- [ ] «a\n- [ ] b»", "«a\nb»"),
+            (TaskList, "😀 «😀»", "- [ ] 😀 «😀»"),
+        ]);
+    }
+
+    #[test]
+    fn quotes() {
+        use FormatCommand::*;
+        check(&[
+            (Quote, "«a»", "> «a»"),
+            (Quote, "> «a»", "«a»"),
+            (Quote, "a«»", "> a«»"),
+            (Quote, "«a\n\nb»", "> «a\n>\n> b»"),
+            (Quote, "> «a\n>\n> b»", "«a\n\nb»"),
+            (Quote, "😀 «😀»", "> 😀 «😀»"),
+        ]);
+    }
+
+    #[test]
+    fn code_blocks() {
+        use FormatCommand::*;
+        check(&[
+            (CodeBlock, "«a»", "```\n«a»\n```"),
+            (CodeBlock, "```\n«a»\n```", "«a»"),
+            (CodeBlock, "```rust\n«a»\n```\nb", "«a»\nb"),
+            (CodeBlock, "«»", "```\n«»\n```"),
+            (CodeBlock, "«a\nb»", "```\n«a\nb»\n```"),
+            (CodeBlock, "«a\n```»", "````\n«a\n```»\n````"),
+            (CodeBlock, "😀\n«😀»", "😀\n```\n«😀»\n```"),
+        ]);
+    }
+
+    #[test]
+    fn links() {
+        let link = |url: &str| FormatCommand::Link {
+            url: url.to_string(),
+        };
+        check(&[
+            (link("u"), "a «b» c", "a [«b»](u) c"),
+            (link("u"), "a [«b»](u) c", "a «b» c"),
+            (link("u"), "[a «b»][r]\n\n[r]: u", "a «b»\n\n[r]: u"),
+            (link("u"), "<«https://a.b»>", "«https://a.b»"),
+            (link("u"), "a «»", "a [«»](u)"),
+            (link("a b"), "«a»", "[«a»](<a b>)"),
+            (link("u"), "«a\nb»", "[«a\nb»](u)"),
+            (link("u"), "😀 «😀»", "😀 [«😀»](u)"),
+        ]);
+    }
+
+    fn newline(text: &str) -> Option<String> {
+        let (text, cursor, _) = split_selection(text);
+        handle_newline(&text, cursor).map(join_selection)
+    }
+
+    #[test]
+    fn newlines() {
+        let cases = [
+            ("- a«»", Some("- a\n- «»")),
+            ("* a«»b", Some("* a\n* «»b")),
+            ("- a\n- «»", Some("- a\n«»")),
+            ("  - «»", Some("«»")),
+            ("- [x] a«»", Some("- [x] a\n- [ ] «»")),
+            ("- [ ] «»", Some("«»")),
+            ("> a«»", Some("> a\n> «»")),
+            ("> > a«»", Some("> > a\n> > «»")),
+            ("> - a«»", Some("> - a\n> - «»")),
+            ("> - «»", Some("> «»")),
+            (">«»", Some("«»")),
+            ("1. a«»\n2. b", Some("1. a\n2. «»\n3. b")),
+            ("1) a«»\n1) b", Some("1) a\n2) «»\n3) b")),
+            (
+                "1. a\n   1. b«»\n   2. c\n2. d",
+                Some("1. a\n   1. b\n   2. «»\n   3. c\n2. d"),
+            ),
+            ("- 😀«»", Some("- 😀\n- «»")),
+            ("a«»", None),
+            ("-«» a", None),
+            ("```\n- a«»\n```", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(newline(text).as_deref(), expected, "{text:?}");
+        }
+    }
+
+    fn indented(text: &str, outdent: bool) -> Option<String> {
+        let (text, start, end) = split_selection(text);
+        indent(&text, start, end, outdent).map(join_selection)
+    }
+
+    #[test]
+    fn indents() {
+        let cases = [
+            ("- a\n- «b»", Some("- a\n  - «b»")),
+            ("- «a»\n- b", None),
+            ("- a\n- «b\n  c»", Some("- a\n  - «b\n    c»")),
+            ("- a\n- «b»\n  - c", Some("- a\n  - «b»\n    - c")),
+            ("- a\n  - b\n- «c»", Some("- a\n  - b\n  - «c»")),
+            ("> - a\n> - «b»", Some("> - a\n>   - «b»")),
+            ("1. a\n2. «b»", Some("1. a\n   1. «b»")),
+            ("1. a\n2. «b»\n3. c", Some("1. a\n   1. «b»\n2. c")),
+            (
+                "1. a\n2. «b\n3. c»\n4. d",
+                Some("1. a\n   1. «b\n   2. c»\n2. d"),
+            ),
+            (
+                "1. a\n   1. b\n2. «c»\n3. d",
+                Some("1. a\n   1. b\n   2. «c»\n2. d"),
+            ),
+            ("9. a\n10. «b»\n11. c", Some("9. a\n   1. «b»\n10. c")),
+            ("😀\n\n- 😀\n- «😀»", Some("😀\n\n- 😀\n  - «😀»")),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(indented(text, false).as_deref(), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn outdents() {
+        let cases = [
+            ("- a\n  - «b»", Some("- a\n- «b»")),
+            ("- «a»", None),
+            ("- a\n  - «b»\n    - c", Some("- a\n- «b»\n  - c")),
+            ("> - a\n>   - «b»", Some("> - a\n> - «b»")),
+            ("1. a\n   1. «b»\n2. c", Some("1. a\n2. «b»\n3. c")),
+            (
+                "1. a\n   1. «b»\n   2. c\n2. d",
+                Some("1. a\n2. «b»\n   1. c\n3. d"),
+            ),
+            (
+                "1. a\n   1. b\n   2. «c»\n2. d",
+                Some("1. a\n   1. b\n2. «c»\n3. d"),
+            ),
+            ("- 😀\n  - «😀»", Some("- 😀\n- «😀»")),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(indented(text, true).as_deref(), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(format_markdown("😀", 1, 1, &FormatCommand::Bold).is_none());
+        assert!(format_markdown("a", 0, 2, &FormatCommand::Bold).is_none());
+    }
+}
@@ -0,0 +1,26 @@
+use ruslin_data::DatabaseError;
+
+#[derive(Debug)]
+pub enum FFIError {
+    Database(DatabaseError),
+    InvalidArgument(String),
+    Network(String),
+}
+
+impl std::fmt::Display for FFIError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FFIError::Database(e) => write!(f, "database error: {e}"),
+            FFIError::InvalidArgument(message) => write!(f, "invalid argument: {message}"),
+            FFIError::Network(message) => write!(f, "network error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for FFIError {}
+
+impl From<DatabaseError> for FFIError {
+    fn from(value: DatabaseError) -> Self {
+        FFIError::Database(value)
+    }
+}
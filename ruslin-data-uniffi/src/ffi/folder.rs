@@ -16,6 +16,19 @@ pub struct FFIFolder {
     pub icon: String,
 }
 
+pub struct FFIFolderNode {
+    pub folder: FFIFolder,
+    /// Notes directly in this folder.
+    pub note_count: i64,
+    /// Notes in this folder and all of its subfolders.
+    pub total_note_count: i64,
+    /// Uncompleted to-dos directly in this folder.
+    pub todo_count: i64,
+    /// Uncompleted to-dos in this folder and all of its subfolders.
+    pub total_todo_count: i64,
+    pub children: Vec<FFIFolderNode>,
+}
+
 impl From<Folder> for FFIFolder {
     fn from(folder: Folder) -> Self {
         Self {
@@ -1,11 +1,16 @@
+mod error;
 mod folder;
 mod note;
 mod resource;
 mod status;
 mod sync_info;
 
-pub use folder::FFIFolder;
-pub use note::{FFIAbbrNote, FFINote, FFISearchNote};
+pub use error::FFIError;
+pub use folder::{FFIFolder, FFIFolderNode};
+pub use note::{
+    FFIAbbrNote, FFINote, FFINoteListOptions, FFINoteSummary, FFISearchNote, NoteSortKey,
+    SortDirection,
+};
 pub use resource::FFIResource;
 pub use status::FFIStatus;
 pub use sync_info::FFISyncInfo;
@@ -8,6 +8,54 @@ pub struct FFISearchNote {
     pub body_highlight_ranges: Vec<i32>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteSortKey {
+    Title,
+    UpdatedTime,
+    CreatedTime,
+    Order,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+pub struct FFINoteListOptions {
+    pub sort_key: NoteSortKey,
+    pub direction: SortDirection,
+    pub offset: i32,
+    /// `None` returns all notes after `offset`.
+    pub limit: Option<i32>,
+    pub show_completed_todos: bool,
+    pub uncompleted_todos_first: bool,
+    /// Maximum number of characters in `FFINoteSummary::excerpt`.
+    pub excerpt_length: i32,
+    pub pinned_first: bool,
+}
+
+/// A note as shown in lists. Tag titles aren't included: ruslin-data has no query for the
+/// tags of a note yet.
+pub struct FFINoteSummary {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub title: String,
+    /// Plain text from the start of the body, markdown syntax removed.
+    pub excerpt: String,
+    pub user_created_time: i64,
+    pub user_updated_time: i64,
+    pub is_conflict: bool,
+    pub is_todo: bool,
+    /// Whether a due date is set. ruslin-data reads Joplin's due timestamp as a flag, so
+    /// the date itself isn't available yet.
+    pub todo_due: bool,
+    pub todo_completed: bool,
+    pub first_image_resource_id: Option<String>,
+    pub has_attachments: bool,
+    pub is_pinned: bool,
+}
+
 pub struct FFIAbbrNote {
     pub id: String,
     pub parent_id: Option<String>,
@@ -54,6 +102,7 @@ pub struct FFINote {
     pub author: String,
     pub source_url: String,
     pub is_todo: bool,
+    /// See `FFINoteSummary::todo_due`.
     pub todo_due: bool,
     pub todo_completed: bool,
     pub source: String,
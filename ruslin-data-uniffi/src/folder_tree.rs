@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+
+use ruslin_data::{AbbrNote, Folder};
+
+use crate::{ffi::FFIFolderNode, note_meta::NoteMeta};
+
+#[derive(Default, Clone, Copy)]
+struct NoteCounts {
+    notes: i64,
+    todos: i64,
+}
+
+/// Builds the folder hierarchy from the flat folder list.
+///
+/// Folders whose parent doesn't exist (e.g. deleted remotely) and folders caught in a
+/// parent cycle are surfaced at the root. Siblings are sorted by title, then by id.
+pub fn build_folder_tree(
+    folders: Vec<Folder>,
+    notes: &[AbbrNote],
+    metas: &[NoteMeta],
+) -> Vec<FFIFolderNode> {
+    let mut counts: HashMap<&str, NoteCounts> = HashMap::new();
+    for (note, meta) in notes.iter().zip(metas) {
+        let Some(parent_id) = note.parent_id.as_deref() else {
+            continue;
+        };
+        let count = counts.entry(parent_id).or_default();
+        count.notes += 1;
+        if meta.is_uncompleted_todo() {
+            count.todos += 1;
+        }
+    }
+    let counts: HashMap<String, NoteCounts> = counts
+        .into_iter()
+        .map(|(id, count)| (id.to_string(), count))
+        .collect();
+
+    let folder_ids: HashSet<String> = folders.iter().map(|f| f.id.clone()).collect();
+    let mut folders = folders;
+    folders.sort_by(compare_folders);
+
+    let mut roots: Vec<Folder> = Vec::new();
+    let mut children: HashMap<String, Vec<Folder>> = HashMap::new();
+    for folder in folders {
+        match folder.parent_id.as_deref() {
+            Some(parent_id) if folder_ids.contains(parent_id) => {
+                children
+                    .entry(parent_id.to_string())
+                    .or_default()
+                    .push(folder);
+            }
+            _ => roots.push(folder),
+        }
+    }
+
+    let mut nodes: Vec<FFIFolderNode> = roots
+        .into_iter()
+        .map(|folder| build_node(folder, &mut children, &counts))
+        .collect();
+    // Whatever is left is only reachable through a parent cycle, which is broken at its
+    // first folder in sort order.
+    while let Some(parent_id) = children
+        .iter()
+        .min_by(|(_, a), (_, b)| compare_folders(&a[0], &b[0]))
+        .map(|(parent_id, _)| parent_id.clone())
+    {
+        let siblings = children.get_mut(&parent_id).unwrap();
+        let folder = siblings.remove(0);
+        if siblings.is_empty() {
+            children.remove(&parent_id);
+        }
+        nodes.push(build_node(folder, &mut children, &counts));
+    }
+    nodes
+}
+
+fn compare_folders(a: &Folder, b: &Folder) -> std::cmp::Ordering {
+    a.title
+        .to_lowercase()
+        .cmp(&b.title.to_lowercase())
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+fn build_node(
+    folder: Folder,
+    children: &mut HashMap<String, Vec<Folder>>,
+    counts: &HashMap<String, NoteCounts>,
+) -> FFIFolderNode {
+    let count = counts.get(&folder.id).copied().unwrap_or_default();
+    let child_nodes: Vec<FFIFolderNode> = children
+        .remove(&folder.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| build_node(child, children, counts))
+        .collect();
+    let total_note_count = count.notes
+        + child_nodes
+            .iter()
+            .map(|node| node.total_note_count)
+            .sum::<i64>();
+    let total_todo_count = count.todos
+        + child_nodes
+            .iter()
+            .map(|node| node.total_todo_count)
+            .sum::<i64>();
+    FFIFolderNode {
+        folder: folder.into(),
+        note_count: count.notes,
+        total_note_count,
+        todo_count: count.todos,
+        total_todo_count,
+        children: child_nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruslin_data::DateTimeTimestamp;
+
+    use super::*;
+
+    fn folder(id: &str, title: &str, parent_id: Option<&str>) -> Folder {
+        let mut folder = Folder::new(title.to_string(), parent_id.map(str::to_string));
+        folder.id = id.to_string();
+        folder
+    }
+
+    fn note(parent_id: &str) -> AbbrNote {
+        AbbrNote {
+            id: format!("note-{parent_id}"),
+            parent_id: Some(parent_id.to_string()),
+            title: String::new(),
+            user_created_time: DateTimeTimestamp::from_timestamp_millis(0),
+            user_updated_time: DateTimeTimestamp::from_timestamp_millis(0),
+        }
+    }
+
+    /// Shows the tree as `id(children)`, with the total note count after the id.
+    fn show(nodes: &[FFIFolderNode]) -> String {
+        let nodes: Vec<String> = nodes
+            .iter()
+            .map(|node| {
+                let mut text = format!("{}:{}", node.folder.id, node.total_note_count);
+                if !node.children.is_empty() {
+                    text.push_str(&format!("({})", show(&node.children)));
+                }
+                text
+            })
+            .collect();
+        nodes.join(" ")
+    }
+
+    fn tree(folders: Vec<Folder>, notes: &[AbbrNote]) -> String {
+        let metas = vec![NoteMeta::default(); notes.len()];
+        show(&build_folder_tree(folders, notes, &metas))
+    }
+
+    #[test]
+    fn sibling_order() {
+        let folders = vec![
+            folder("c", "beta", Some("root")),
+            folder("b", "Alpha", Some("root")),
+            folder("a", "beta", Some("root")),
+            folder("root", "Root", Some("")),
+        ];
+        assert_eq!(tree(folders, &[note("a")]), "root:1(b:0 a:1 c:0)");
+    }
+
+    #[test]
+    fn missing_parent() {
+        let folders = vec![
+            folder("a", "A", None),
+            folder("b", "B", Some("deleted")),
+            folder("c", "C", Some("b")),
+        ];
+        assert_eq!(tree(folders, &[note("c")]), "a:0 b:1(c:1)");
+    }
+
+    #[test]
+    fn self_parent() {
+        let folders = vec![folder("a", "A", Some("a")), folder("b", "B", Some("a"))];
+        assert_eq!(tree(folders, &[note("a"), note("b")]), "a:2(b:1)");
+    }
+
+    #[test]
+    fn two_folder_cycle() {
+        let folders = vec![
+            folder("root", "Root", None),
+            folder("a", "A", Some("b")),
+            folder("b", "B", Some("a")),
+            folder("c", "C", Some("b")),
+        ];
+        let notes = [note("a"), note("b"), note("c")];
+        assert_eq!(tree(folders, &notes), "root:0 a:3(b:2(c:1))");
+    }
+
+    #[test]
+    fn todo_counts() {
+        let folders = vec![folder("a", "A", None), folder("b", "B", Some("a"))];
+        let notes = [note("a"), note("b"), note("b")];
+        let metas = [
+            NoteMeta {
+                is_todo: true,
+                ..Default::default()
+            },
+            NoteMeta {
+                is_todo: true,
+                todo_completed: true,
+                ..Default::default()
+            },
+            NoteMeta::default(),
+        ];
+        let nodes = build_folder_tree(folders, &notes, &metas);
+        assert_eq!(nodes[0].todo_count, 1);
+        assert_eq!(nodes[0].total_todo_count, 1);
+        assert_eq!(nodes[0].children[0].note_count, 2);
+        assert_eq!(nodes[0].children[0].todo_count, 0);
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io;
+use std::ops::Range;
 
 use pulldown_cmark::escape::{escape_href, escape_html, StrWrite};
 use pulldown_cmark::CowStr;
@@ -8,13 +9,162 @@ use pulldown_cmark::{Alignment, CodeBlockKind, Event, LinkType, Tag};
 use pulldown_cmark::{Options, Parser};
 use ruslin_data::{ModelType, RuslinData};
 
-pub fn parse_markdown_to_preview_html(data: &RuslinData, text: String) -> String {
-    let parser = Parser::new_ext(&text, Options::all());
+use crate::diagram;
+use crate::math::MaskedText;
+use crate::outline::{self, FFIOutlineHeading, Heading};
+use crate::utf16::utf8_to_utf16_offsets;
+
+pub struct PreviewOptions {
+    /// Emits `data-source-line` (0-based) and `data-source-offset` (UTF-16) attributes on
+    /// block-level elements, for scroll sync with the editor.
+    pub source_positions: bool,
+    /// Replaces a `[toc]` paragraph with a linked table of contents.
+    pub table_of_contents: bool,
+}
+
+pub fn parse_markdown_to_preview_html(
+    data: &RuslinData,
+    text: String,
+    options: PreviewOptions,
+) -> String {
+    let masked = MaskedText::new(&text);
+    let parser = Parser::new_ext(&masked.text, Options::all()).into_offset_iter();
+    let (events, numbers) = move_footnotes(parser);
     let mut html_output: String = String::with_capacity(text.len() * 3 / 2);
-    push_html(&mut html_output, parser, data);
+    HtmlWriter::new(
+        events.into_iter(),
+        &mut html_output,
+        &text,
+        &masked,
+        &options,
+        numbers,
+        data,
+    )
+    .run()
+    .unwrap();
     html_output
 }
 
+type Events<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// Moves the footnote definitions into a trailing `<section class="footnotes">`, GitHub
+/// style. Footnotes are numbered by the order of their first reference, definitions that
+/// are never referenced are dropped. Returns the events and the footnote numbers.
+fn move_footnotes<'a>(
+    iter: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+) -> (Events<'a>, HashMap<CowStr<'a>, usize>) {
+    let mut events = Vec::new();
+    let mut definitions: HashMap<CowStr<'a>, Events<'a>> = HashMap::new();
+    let mut definition: Option<(CowStr<'a>, Events<'a>)> = None;
+    // Definitions can be nested, e.g. right after a heading in a definition. Those stay in
+    // the outer one.
+    let mut nesting = 0;
+    for (event, range) in iter {
+        match (&mut definition, event) {
+            (None, Start(Tag::FootnoteDefinition(name))) => {
+                let start = Start(Tag::FootnoteDefinition(name.clone()));
+                definition = Some((name, vec![(start, range)]));
+            }
+            (Some((_, definition_events)), event @ Start(Tag::FootnoteDefinition(_))) => {
+                nesting += 1;
+                definition_events.push((event, range));
+            }
+            (Some((_, definition_events)), event @ End(Tag::FootnoteDefinition(_)))
+                if nesting > 0 =>
+            {
+                nesting -= 1;
+                definition_events.push((event, range));
+            }
+            (Some(_), End(Tag::FootnoteDefinition(name))) => {
+                let (_, mut definition_events) = definition.take().unwrap();
+                definition_events.push((End(Tag::FootnoteDefinition(name.clone())), range));
+                // The first definition wins, like for link reference definitions.
+                definitions.entry(name).or_insert(definition_events);
+            }
+            (Some((_, definition_events)), event) => definition_events.push((event, range)),
+            (None, event) => events.push((event, range)),
+        }
+    }
+
+    // References inside of footnotes are numbered after the ones in the text.
+    let mut order: Vec<CowStr<'a>> = Vec::new();
+    let mut numbers = HashMap::new();
+    let mut number_references = |events: &Events<'a>, order: &mut Vec<CowStr<'a>>| {
+        for (event, _) in events {
+            if let FootnoteReference(name) = event {
+                if definitions.contains_key(name) && !numbers.contains_key(name) {
+                    numbers.insert(name.clone(), order.len() + 1);
+                    order.push(name.clone());
+                }
+            }
+        }
+    };
+    number_references(&events, &mut order);
+    let mut index = 0;
+    while index < order.len() {
+        number_references(&definitions[&order[index]], &mut order);
+        index += 1;
+    }
+    if order.is_empty() {
+        return (events, numbers);
+    }
+
+    let end = events.last().map(|(_, range)| range.end).unwrap_or(0);
+    events.push((
+        Html("<section class=\"footnotes\">\n<ol>\n".into()),
+        end..end,
+    ));
+    for (index, name) in order.iter().enumerate() {
+        let mut definition_events = definitions.remove(name).unwrap();
+        let backref = format!(
+            " <a href=\"#fnref-{}\" class=\"footnote-backref\">↩</a>",
+            index + 1
+        );
+        // The backlink goes into the last paragraph if there is one.
+        let position = match definition_events[definition_events.len() - 2] {
+            (End(Tag::Paragraph), _) => definition_events.len() - 2,
+            _ => definition_events.len() - 1,
+        };
+        let range = definition_events[position].1.clone();
+        definition_events.insert(position, (Html(backref.into()), range.start..range.start));
+        events.extend(definition_events);
+    }
+    events.push((Html("</ol>\n</section>\n".into()), end..end));
+    (events, numbers)
+}
+
+struct SourceMap {
+    /// UTF-16 offset of every byte of the source, see `utf8_to_utf16_offsets`.
+    utf16_offsets: Vec<usize>,
+    /// Byte offsets of the line starts, only filled if source positions are enabled.
+    line_starts: Vec<usize>,
+    source_positions: bool,
+}
+
+impl SourceMap {
+    fn new(text: &str, options: &PreviewOptions) -> Self {
+        let line_starts = if options.source_positions {
+            std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            utf16_offsets: utf8_to_utf16_offsets(text),
+            line_starts,
+            source_positions: options.source_positions,
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        }
+    }
+}
+
 // https://github.com/raphlinus/pulldown-cmark/blob/5c7881c45c1b9cb35e8c9417f09521f7a517b8cf/src/html.rs
 
 enum TableState {
@@ -35,16 +185,33 @@ struct HtmlWriter<'a, I, W> {
     table_state: TableState,
     table_alignments: Vec<Alignment>,
     table_cell_index: usize,
+    /// Footnote numbers, only referenced footnotes with a definition are numbered.
     numbers: HashMap<CowStr<'a>, usize>,
+    /// Number of references written for each footnote, for unique reference ids.
+    reference_counts: HashMap<usize, usize>,
+    source: &'a str,
+    masked: &'a MaskedText,
+    source_map: SourceMap,
+    /// All headings of the source, used for their ids.
+    headings: Vec<Heading>,
+    table_of_contents: bool,
     data: &'a RuslinData,
 }
 
 impl<'a, I, W> HtmlWriter<'a, I, W>
 where
-    I: Iterator<Item = Event<'a>>,
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
     W: StrWrite,
 {
-    fn new(iter: I, writer: W, data: &'a RuslinData) -> Self {
+    fn new(
+        iter: I,
+        writer: W,
+        source: &'a str,
+        masked: &'a MaskedText,
+        options: &PreviewOptions,
+        numbers: HashMap<CowStr<'a>, usize>,
+        data: &'a RuslinData,
+    ) -> Self {
         Self {
             iter,
             writer,
@@ -52,7 +219,13 @@ where
             table_state: TableState::Head,
             table_alignments: vec![],
             table_cell_index: 0,
-            numbers: HashMap::new(),
+            numbers,
+            reference_counts: HashMap::new(),
+            source,
+            masked,
+            source_map: SourceMap::new(source, options),
+            headings: outline::headings(source, masked),
+            table_of_contents: options.table_of_contents,
             data,
         }
     }
@@ -75,10 +248,10 @@ where
     }
 
     fn run(mut self) -> io::Result<()> {
-        while let Some(event) = self.iter.next() {
+        while let Some((event, range)) = self.iter.next() {
             match event {
                 Start(tag) => {
-                    self.start_tag(tag)?;
+                    self.start_tag(tag, range)?;
                 }
                 End(tag) => {
                     self.end_tag(tag)?;
@@ -87,11 +260,23 @@ where
                     escape_html(&mut self.writer, &text)?;
                     self.end_newline = text.ends_with('\n');
                 }
-                Code(text) => {
-                    self.write("<code>")?;
-                    escape_html(&mut self.writer, &text)?;
-                    self.write("</code>")?;
-                }
+                Code(text) => match self.masked.span(&range) {
+                    // Left for a JS renderer such as KaTeX, with the unmodified TeX.
+                    Some(span) => {
+                        if span.display {
+                            self.write("<span class=\"math display\">")?;
+                        } else {
+                            self.write("<span class=\"math inline\">")?;
+                        }
+                        escape_html(&mut self.writer, span.tex(self.source))?;
+                        self.write("</span>")?;
+                    }
+                    None => {
+                        self.write("<code>")?;
+                        escape_html(&mut self.writer, &text)?;
+                        self.write("</code>")?;
+                    }
+                },
                 Html(html) => {
                     self.write(&html)?;
                 }
@@ -108,35 +293,83 @@ where
                         self.write("\n<hr />\n")?;
                     }
                 }
-                FootnoteReference(name) => {
-                    let len = self.numbers.len() + 1;
-                    self.write("<sup class=\"footnote-reference\"><a href=\"#")?;
-                    escape_html(&mut self.writer, &name)?;
-                    self.write("\">")?;
-                    let number = *self.numbers.entry(name).or_insert(len);
-                    write!(&mut self.writer, "{number}")?;
-                    self.write("</a></sup>")?;
-                }
-                TaskListMarker(true) => {
-                    self.write("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n")?;
-                }
-                TaskListMarker(false) => {
-                    self.write("<input disabled=\"\" type=\"checkbox\"/>\n")?;
+                FootnoteReference(name) => match self.numbers.get(&name) {
+                    Some(&number) => {
+                        let count = self.reference_counts.entry(number).or_insert(0);
+                        *count += 1;
+                        let id = if *count == 1 {
+                            format!("fnref-{number}")
+                        } else {
+                            format!("fnref-{number}-{count}")
+                        };
+                        write!(
+                            &mut self.writer,
+                            "<sup class=\"footnote-reference\"><a href=\"#fn-{number}\" id=\"{id}\">{number}</a></sup>"
+                        )?;
+                    }
+                    // Unresolved references are left as they were written.
+                    None => {
+                        self.write("[^")?;
+                        escape_html(&mut self.writer, &name)?;
+                        self.write("]")?;
+                    }
+                },
+                TaskListMarker(checked) => {
+                    // The UTF-16 offset of the marker lets the app toggle it in the source
+                    // with `toggle_task`.
+                    self.write("<input type=\"checkbox\" data-task-offset=\"")?;
+                    write!(
+                        &mut self.writer,
+                        "{}",
+                        self.source_map.utf16_offsets[range.start]
+                    )?;
+                    if checked {
+                        self.write("\" checked=\"\"/>\n")?;
+                    } else {
+                        self.write("\"/>\n")?;
+                    }
                 }
             }
         }
         Ok(())
     }
 
+    /// Writes the source position attributes of a block-level element, if enabled.
+    fn write_source_position(&mut self, range: &Range<usize>) -> io::Result<()> {
+        if !self.source_map.source_positions {
+            return Ok(());
+        }
+        let line = self.source_map.line(range.start);
+        let offset = self.source_map.utf16_offsets[range.start];
+        write!(
+            &mut self.writer,
+            " data-source-line=\"{line}\" data-source-offset=\"{offset}\""
+        )
+    }
+
     /// Writes the start of an HTML tag.
-    fn start_tag(&mut self, tag: Tag<'a>) -> io::Result<()> {
+    fn start_tag(&mut self, tag: Tag<'a>, range: Range<usize>) -> io::Result<()> {
         match tag {
+            Tag::Paragraph if self.is_toc_placeholder(&range) => {
+                self.skip_to_end()?;
+                let outline = outline::outline(&self.headings);
+                if !self.end_newline {
+                    self.write_newline()?;
+                }
+                self.write("<nav class=\"table-of-contents\"")?;
+                self.write_source_position(&range)?;
+                self.write(">\n")?;
+                self.write_toc(&outline)?;
+                self.write("</nav>\n")
+            }
             Tag::Paragraph => {
                 if self.end_newline {
-                    self.write("<p>")
+                    self.write("<p")?;
                 } else {
-                    self.write("\n<p>")
+                    self.write("\n<p")?;
                 }
+                self.write_source_position(&range)?;
+                self.write(">")
             }
             Tag::Heading(level, id, classes) => {
                 if self.end_newline {
@@ -146,9 +379,16 @@ where
                     self.write("\n<")?;
                 }
                 write!(&mut self.writer, "{level}")?;
+                let offset = self.source_map.utf16_offsets[range.start];
+                let id = self
+                    .headings
+                    .iter()
+                    .find(|heading| heading.offset == offset)
+                    .map(|heading| heading.id.clone())
+                    .or(id.map(|id| id.to_string()));
                 if let Some(id) = id {
                     self.write(" id=\"")?;
-                    escape_html(&mut self.writer, id)?;
+                    escape_html(&mut self.writer, &id)?;
                     self.write("\"")?;
                 }
                 let mut classes = classes.iter();
@@ -161,11 +401,14 @@ where
                     }
                     self.write("\"")?;
                 }
+                self.write_source_position(&range)?;
                 self.write(">")
             }
             Tag::Table(alignments) => {
                 self.table_alignments = alignments;
-                self.write("<table>")
+                self.write("<table")?;
+                self.write_source_position(&range)?;
+                self.write(">")
             }
             Tag::TableHead => {
                 self.table_state = TableState::Head;
@@ -194,27 +437,39 @@ where
             }
             Tag::BlockQuote => {
                 if self.end_newline {
-                    self.write("<blockquote>\n")
+                    self.write("<blockquote")?;
                 } else {
-                    self.write("\n<blockquote>\n")
+                    self.write("\n<blockquote")?;
                 }
+                self.write_source_position(&range)?;
+                self.write(">\n")
+            }
+            Tag::CodeBlock(CodeBlockKind::Fenced(info))
+                if diagram::diagram_kind(info.split(' ').next().unwrap()).is_some() =>
+            {
+                let lang = info.split(' ').next().unwrap();
+                let kind = diagram::diagram_kind(lang).unwrap();
+                self.write_diagram(kind, lang, range)
             }
             Tag::CodeBlock(info) => {
                 if !self.end_newline {
                     self.write_newline()?;
                 }
+                self.write("<pre")?;
+                self.write_source_position(&range)?;
+                self.write(">")?;
                 match info {
                     CodeBlockKind::Fenced(info) => {
                         let lang = info.split(' ').next().unwrap();
                         if lang.is_empty() {
-                            self.write("<pre><code>")
+                            self.write("<code>")
                         } else {
-                            self.write("<pre><code class=\"language-")?;
+                            self.write("<code class=\"language-")?;
                             escape_html(&mut self.writer, lang)?;
                             self.write("\">")
                         }
                     }
-                    CodeBlockKind::Indented => self.write("<pre><code>"),
+                    CodeBlockKind::Indented => self.write("<code>"),
                 }
             }
             Tag::List(Some(1)) => {
@@ -242,10 +497,12 @@ where
             }
             Tag::Item => {
                 if self.end_newline {
-                    self.write("<li>")
+                    self.write("<li")?;
                 } else {
-                    self.write("\n<li>")
+                    self.write("\n<li")?;
                 }
+                self.write_source_position(&range)?;
+                self.write(">")
             }
             Tag::Emphasis => self.write("<em>"),
             Tag::Strong => self.write("<strong>"),
@@ -298,17 +555,15 @@ where
                 self.write("\" />")
             }
             Tag::FootnoteDefinition(name) => {
-                if self.end_newline {
-                    self.write("<div class=\"footnote-definition\" id=\"")?;
-                } else {
-                    self.write("\n<div class=\"footnote-definition\" id=\"")?;
+                if !self.end_newline {
+                    self.write_newline()?;
+                }
+                // Nested definitions aren't numbered.
+                match self.numbers.get(&name) {
+                    Some(number) => write!(&mut self.writer, "<li id=\"fn-{number}\">")?,
+                    None => self.write("<li>")?,
                 }
-                escape_html(&mut self.writer, &name)?;
-                self.write("\"><sup class=\"footnote-definition-label\">")?;
-                let len = self.numbers.len() + 1;
-                let number = *self.numbers.entry(name).or_insert(len);
-                write!(&mut self.writer, "{number}")?;
-                self.write("</sup>")
+                self.write_newline()
             }
         }
     }
@@ -373,7 +628,88 @@ where
             }
             Tag::Image(_, _, _) => (), // shouldn't happen, handled in start
             Tag::FootnoteDefinition(_) => {
-                self.write("</div>\n")?;
+                if !self.end_newline {
+                    self.write_newline()?;
+                }
+                self.write("</li>\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_toc_placeholder(&self, range: &Range<usize>) -> bool {
+        self.table_of_contents
+            && self.source[range.clone()]
+                .trim()
+                .eq_ignore_ascii_case("[toc]")
+    }
+
+    fn write_toc(&mut self, outline: &[FFIOutlineHeading]) -> io::Result<()> {
+        if outline.is_empty() {
+            return Ok(());
+        }
+        self.write("<ul>\n")?;
+        for heading in outline {
+            self.write("<li><a href=\"#")?;
+            escape_href(&mut self.writer, &heading.id)?;
+            self.write("\">")?;
+            escape_html(&mut self.writer, &heading.text)?;
+            self.write("</a>")?;
+            if !heading.children.is_empty() {
+                self.write_newline()?;
+                self.write_toc(&heading.children)?;
+            }
+            self.write("</li>\n")?;
+        }
+        self.write("</ul>\n")
+    }
+
+    /// Writes a diagram code block, consuming its end tag. The diagram is rendered to SVG
+    /// when possible, otherwise the source is kept for a JS renderer in the preview.
+    fn write_diagram(&mut self, kind: &str, lang: &str, range: Range<usize>) -> io::Result<()> {
+        let mut source = String::new();
+        for (event, _) in self.iter.by_ref() {
+            match event {
+                Text(text) => source.push_str(&text),
+                End(_) => break,
+                _ => {}
+            }
+        }
+        if !self.end_newline {
+            self.write_newline()?;
+        }
+        write!(
+            &mut self.writer,
+            "<div class=\"diagram\" data-diagram=\"{kind}\""
+        )?;
+        self.write_source_position(&range)?;
+        self.write(">")?;
+        match diagram::render_svg(kind, &source) {
+            Some(svg) => self.write(&svg)?,
+            None => {
+                self.write("<pre><code class=\"language-")?;
+                escape_html(&mut self.writer, lang)?;
+                self.write("\">")?;
+                escape_html(&mut self.writer, &source)?;
+                self.write("</code></pre>")?;
+            }
+        }
+        self.write("</div>\n")
+    }
+
+    /// Skips all events up to and including the end tag of the current element.
+    fn skip_to_end(&mut self) -> io::Result<()> {
+        let mut nest = 0;
+        for (event, _) in self.iter.by_ref() {
+            match event {
+                Start(_) => nest += 1,
+                End(_) => {
+                    if nest == 0 {
+                        break;
+                    }
+                    nest -= 1;
+                }
+                _ => {}
             }
         }
         Ok(())
@@ -382,7 +718,7 @@ where
     // run raw text, consuming end tag
     fn raw_text(&mut self) -> io::Result<()> {
         let mut nest = 0;
-        while let Some(event) = self.iter.next() {
+        while let Some((event, _)) = self.iter.next() {
             match event {
                 Start(_) => nest += 1,
                 End(_) => {
@@ -398,11 +734,14 @@ where
                 SoftBreak | HardBreak | Rule => {
                     self.write(" ")?;
                 }
-                FootnoteReference(name) => {
-                    let len = self.numbers.len() + 1;
-                    let number = *self.numbers.entry(name).or_insert(len);
-                    write!(&mut self.writer, "[{number}]")?;
-                }
+                FootnoteReference(name) => match self.numbers.get(&name) {
+                    Some(number) => write!(&mut self.writer, "[{number}]")?,
+                    None => {
+                        self.write("[^")?;
+                        escape_html(&mut self.writer, &name)?;
+                        self.write("]")?;
+                    }
+                },
                 TaskListMarker(true) => self.write("[x]")?,
                 TaskListMarker(false) => self.write("[ ]")?,
             }
@@ -411,9 +750,48 @@ where
     }
 }
 
-pub fn push_html<'a, I>(s: &mut String, iter: I, data: &'a RuslinData)
-where
-    I: Iterator<Item = Event<'a>>,
-{
-    HtmlWriter::new(iter, s, data).run().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn render(name: &str, text: &str) -> String {
+        let dir = TempDir::new(name);
+        let options = PreviewOptions {
+            source_positions: false,
+            table_of_contents: false,
+        };
+        parse_markdown_to_preview_html(&dir.data(), text.to_string(), options)
+    }
+
+    #[test]
+    fn nested_footnote_definition() {
+        // The heading ends the paragraph, so `[^b]` starts a definition inside `[^a]`.
+        let html = render(
+            "nested",
+            "Text[^a][^b]\n\n[^a]: # Outer\n[^b]: Inner\n\nRest of outer\n",
+        );
+        let section = html.find("<section class=\"footnotes\">").unwrap();
+        assert!(html[..section].starts_with("<p>Text<sup"));
+        assert!(!html[..section].contains("Rest of outer"));
+        assert!(
+            html[section..].contains("<h1 id=\"outer\">Outer</h1>\n<li>\n<p>Inner</p>\n</li>\n")
+        );
+        assert!(html[section..].contains("<p>Rest of outer <a href=\"#fnref-1\""));
+    }
+
+    #[test]
+    fn task_list_markers() {
+        let html = render("tasks", "- [ ] 📝 a\n- [x] b\n");
+        assert!(html.contains("<input type=\"checkbox\" data-task-offset=\"2\"/>"));
+        // The emoji is two UTF-16 code units.
+        assert!(html.contains("<input type=\"checkbox\" data-task-offset=\"13\" checked=\"\"/>"));
+        assert!(!html.contains("disabled"));
+    }
+
+    #[test]
+    fn unreferenced_footnote_definition() {
+        let html = render("unreferenced", "Text\n\n[^a]: Never referenced\n");
+        assert_eq!(html.trim(), "<p>Text</p>");
+    }
 }
@@ -0,0 +1,861 @@
+use std::collections::HashMap;
+
+use url::Url;
+
+use crate::dom::{parse_html, Element, Node};
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Elements without content the reader would want in a note.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "button", "canvas", "embed", "head", "iframe", "link", "meta", "noscript", "object", "script",
+    "select", "style", "svg", "template", "textarea", "title",
+];
+
+fn is_html_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
+}
+
+#[derive(Clone, Copy, Default)]
+struct InlineState {
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    link: bool,
+    table_cell: bool,
+}
+
+struct Block {
+    markdown: String,
+    list: bool,
+}
+
+/// Converts HTML to CommonMark with the GFM extensions the editor renders, like tables, task
+/// lists and strikethrough.
+pub struct HtmlToMarkdown {
+    base_url: Option<Url>,
+    /// Replacements for image sources, e.g. `:/id` links of downloaded resources, keyed by the
+    /// resolved URL.
+    pub image_destinations: HashMap<String, String>,
+}
+
+impl HtmlToMarkdown {
+    pub fn new(base_url: Option<&str>) -> Self {
+        Self {
+            base_url: base_url.and_then(|url| Url::parse(url.trim()).ok()),
+            image_destinations: HashMap::new(),
+        }
+    }
+
+    /// Resolves relative URLs against the base URL. They're kept as is without one.
+    pub fn resolve(&self, url: &str) -> String {
+        let url = url.trim();
+        match &self.base_url {
+            Some(base) => base
+                .join(url)
+                .map_or_else(|_| url.to_string(), String::from),
+            None => url.to_string(),
+        }
+    }
+
+    /// Returns the resolved sources of all images, without duplicates.
+    pub fn image_urls(&self, document: &Element) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        document.descendants(&mut |element| {
+            if element.name != "img" {
+                return;
+            }
+            if let Some(source) = image_source(element) {
+                let url = self.resolve(source);
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        });
+        urls
+    }
+
+    pub fn convert(&self, document: &Element) -> String {
+        let mut blocks = Vec::new();
+        self.blocks(document, &mut blocks);
+        join_blocks(&blocks)
+    }
+
+    /// Converts the elements as blocks, e.g. the parts of a page with its main content.
+    pub fn convert_elements(&self, elements: &[&Element]) -> String {
+        let mut blocks = Vec::new();
+        for element in elements {
+            if BLOCK_ELEMENTS.contains(&element.name.as_str()) {
+                self.block(element, &mut blocks);
+            } else {
+                let mut inline = String::new();
+                self.inlines(element, &mut inline, InlineState::default());
+                push_paragraph(&mut inline, &mut blocks);
+            }
+        }
+        join_blocks(&blocks)
+    }
+
+    fn blocks(&self, element: &Element, blocks: &mut Vec<Block>) {
+        let mut inline = String::new();
+        for child in &element.children {
+            match child {
+                Node::Element(child) if SKIPPED_ELEMENTS.contains(&child.name.as_str()) => {}
+                Node::Element(child) if BLOCK_ELEMENTS.contains(&child.name.as_str()) => {
+                    push_paragraph(&mut inline, blocks);
+                    self.block(child, blocks);
+                }
+                _ => self.inline(child, &mut inline, InlineState::default()),
+            }
+        }
+        push_paragraph(&mut inline, blocks);
+    }
+
+    fn block(&self, element: &Element, blocks: &mut Vec<Block>) {
+        let markdown = match element.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = usize::from(element.name.as_bytes()[1] - b'0');
+                let mut content = String::new();
+                self.inlines(element, &mut content, InlineState::default());
+                let mut content = collapse_spaces(&content.replace("\\\n", " "));
+                if content.is_empty() {
+                    return;
+                }
+                // A trailing `#` would be read as a closing sequence.
+                if content.ends_with('#') {
+                    content.insert(content.len() - 1, '\\');
+                }
+                format!("{} {content}", "#".repeat(level))
+            }
+            "hr" => "---".to_string(),
+            "pre" => code_block(element),
+            "blockquote" => {
+                let mut quoted = Vec::new();
+                self.blocks(element, &mut quoted);
+                if quoted.is_empty() {
+                    return;
+                }
+                prefix_lines(&join_blocks(&quoted), "> ", ">")
+            }
+            "ul" | "ol" => {
+                let list = self.list(element);
+                if list.is_empty() {
+                    return;
+                }
+                blocks.push(Block {
+                    markdown: list,
+                    list: true,
+                });
+                return;
+            }
+            "table" => {
+                if let Some(caption) = element.elements().find(|child| child.name == "caption") {
+                    self.blocks(caption, blocks);
+                }
+                match self.table(element) {
+                    Some(table) => table,
+                    None => return,
+                }
+            }
+            _ => {
+                self.blocks(element, blocks);
+                return;
+            }
+        };
+        blocks.push(Block {
+            markdown,
+            list: false,
+        });
+    }
+
+    fn list(&self, element: &Element) -> String {
+        let ordered = element.name == "ol";
+        let mut number = element
+            .attribute("start")
+            .and_then(|start| start.trim().parse::<u64>().ok())
+            .unwrap_or(1);
+        let mut items: Vec<Vec<Block>> = Vec::new();
+        for child in &element.children {
+            match child {
+                // Some editors put nested lists next to the items instead of inside them.
+                Node::Element(child) if matches!(child.name.as_str(), "ul" | "ol") => {
+                    let nested = self.list(child);
+                    if nested.is_empty() {
+                        continue;
+                    }
+                    let block = Block {
+                        markdown: nested,
+                        list: true,
+                    };
+                    match items.last_mut() {
+                        Some(item) => item.push(block),
+                        None => items.push(vec![block]),
+                    }
+                }
+                Node::Element(child) if child.name == "li" => {
+                    let mut item = Vec::new();
+                    self.blocks(child, &mut item);
+                    items.push(item);
+                }
+                Node::Text(text) if text.chars().all(is_html_whitespace) => {}
+                _ => {
+                    let mut item = Vec::new();
+                    let mut inline = String::new();
+                    self.inline(child, &mut inline, InlineState::default());
+                    push_paragraph(&mut inline, &mut item);
+                    if !item.is_empty() {
+                        items.push(item);
+                    }
+                }
+            }
+        }
+
+        let mut list = String::new();
+        for item in items {
+            let marker = if ordered {
+                let marker = format!("{number}.");
+                number += 1;
+                marker
+            } else {
+                "-".to_string()
+            };
+            if !list.is_empty() {
+                list.push('\n');
+            }
+            let mut content = String::new();
+            for (index, block) in item.iter().enumerate() {
+                if index > 0 {
+                    // A nested list right after the text keeps the item tight.
+                    content.push_str(if block.list && !item[index - 1].list {
+                        "\n"
+                    } else {
+                        "\n\n"
+                    });
+                }
+                content.push_str(&block.markdown);
+            }
+            if content.is_empty() {
+                list.push_str(&marker);
+                continue;
+            }
+            let indentation = " ".repeat(marker.len() + 1);
+            for (index, line) in content.split('\n').enumerate() {
+                if index == 0 {
+                    list.push_str(&marker);
+                    list.push(' ');
+                } else {
+                    list.push('\n');
+                    if !line.is_empty() {
+                        list.push_str(&indentation);
+                    }
+                }
+                list.push_str(line);
+            }
+        }
+        list
+    }
+
+    fn table(&self, element: &Element) -> Option<String> {
+        let mut rows: Vec<Vec<(String, &'static str)>> = Vec::new();
+        self.table_rows(element, &mut rows);
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return None;
+        }
+        let mut table = String::new();
+        for (index, row) in rows.iter().enumerate() {
+            if index > 0 {
+                table.push('\n');
+            }
+            table.push('|');
+            for column in 0..columns {
+                let cell = row.get(column).map_or("", |(cell, _)| cell.as_str());
+                table.push(' ');
+                table.push_str(cell);
+                if !cell.is_empty() {
+                    table.push(' ');
+                }
+                table.push('|');
+            }
+            if index == 0 {
+                table.push_str("\n|");
+                for column in 0..columns {
+                    let alignment = row.get(column).map_or("---", |&(_, alignment)| alignment);
+                    table.push(' ');
+                    table.push_str(alignment);
+                    table.push_str(" |");
+                }
+            }
+        }
+        Some(table)
+    }
+
+    fn table_rows(&self, element: &Element, rows: &mut Vec<Vec<(String, &'static str)>>) {
+        for child in element.elements() {
+            match child.name.as_str() {
+                "thead" | "tbody" | "tfoot" => self.table_rows(child, rows),
+                "tr" => {
+                    let mut row = Vec::new();
+                    for cell in child.elements() {
+                        if !matches!(cell.name.as_str(), "td" | "th") {
+                            continue;
+                        }
+                        let state = InlineState {
+                            table_cell: true,
+                            ..Default::default()
+                        };
+                        let mut content = String::new();
+                        self.inlines(cell, &mut content, state);
+                        let content = collapse_spaces(&content.replace('\n', " "));
+                        row.push((content, cell_alignment(cell)));
+                        let span = cell
+                            .attribute("colspan")
+                            .and_then(|span| span.trim().parse::<usize>().ok())
+                            .unwrap_or(1);
+                        for _ in 1..span.min(64) {
+                            row.push((String::new(), "---"));
+                        }
+                    }
+                    rows.push(row);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn inlines(&self, element: &Element, out: &mut String, state: InlineState) {
+        for child in &element.children {
+            self.inline(child, out, state);
+        }
+    }
+
+    fn inline(&self, node: &Node, out: &mut String, state: InlineState) {
+        let element = match node {
+            Node::Text(text) => {
+                push_text(text, out, state);
+                return;
+            }
+            Node::Element(element) => element,
+        };
+        match element.name.as_str() {
+            name if SKIPPED_ELEMENTS.contains(&name) => {}
+            "br" if state.table_cell => out.push(' '),
+            "br" => out.push_str("\\\n"),
+            "strong" | "b" if !state.strong => {
+                let state = InlineState {
+                    strong: true,
+                    ..state
+                };
+                self.delimited(element, out, state, "**");
+            }
+            "em" | "i" | "cite" | "dfn" | "var" if !state.emphasis => {
+                let state = InlineState {
+                    emphasis: true,
+                    ..state
+                };
+                self.delimited(element, out, state, "*");
+            }
+            "del" | "s" | "strike" if !state.strikethrough => {
+                let state = InlineState {
+                    strikethrough: true,
+                    ..state
+                };
+                self.delimited(element, out, state, "~~");
+            }
+            "code" | "kbd" | "samp" | "tt" => {
+                let code = collapse_whitespace(&text_content(element));
+                if code.is_empty() {
+                    return;
+                }
+                let span = code_span(code.trim_matches(' '));
+                if code.starts_with(' ') {
+                    out.push(' ');
+                }
+                if state.table_cell {
+                    out.push_str(&span.replace('|', "\\|"));
+                } else {
+                    out.push_str(&span);
+                }
+                if code.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            "a" => self.link(element, out, state),
+            "img" => self.image(element, out),
+            "input" => {
+                if element
+                    .attribute("type")
+                    .is_some_and(|kind| kind.eq_ignore_ascii_case("checkbox"))
+                {
+                    let checked = element.attribute("checked").is_some();
+                    out.push_str(if checked { "[x] " } else { "[ ] " });
+                }
+            }
+            name if BLOCK_ELEMENTS.contains(&name) => {
+                out.push(' ');
+                self.inlines(element, out, state);
+                out.push(' ');
+            }
+            _ => self.inlines(element, out, state),
+        }
+    }
+
+    /// Wraps the content in emphasis delimiters, which must be next to the content.
+    fn delimited(&self, element: &Element, out: &mut String, state: InlineState, delimiter: &str) {
+        let mut content = String::new();
+        self.inlines(element, &mut content, state);
+        let trimmed = content.trim_matches(' ');
+        if trimmed.is_empty() {
+            out.push_str(&content);
+            return;
+        }
+        if content.starts_with(' ') {
+            out.push(' ');
+        }
+        out.push_str(delimiter);
+        out.push_str(trimmed);
+        out.push_str(delimiter);
+        if content.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+
+    fn link(&self, element: &Element, out: &mut String, state: InlineState) {
+        let href = element.attribute("href").map(str::trim).filter(|href| {
+            !href.is_empty()
+                && !href
+                    .get(..11)
+                    .is_some_and(|scheme| scheme.eq_ignore_ascii_case("javascript:"))
+        });
+        let mut text = String::new();
+        self.inlines(
+            element,
+            &mut text,
+            InlineState {
+                link: true,
+                ..state
+            },
+        );
+        // Links can't be nested, so only the text of an inner link is kept.
+        let Some(href) = href.filter(|_| !state.link) else {
+            out.push_str(&text);
+            return;
+        };
+        let trimmed = text.trim_matches(' ');
+        if trimmed.is_empty() {
+            return;
+        }
+        let url = self.resolve(href);
+        if text.starts_with(' ') {
+            out.push(' ');
+        }
+        let plain_text = collapse_whitespace(&text_content(element));
+        let is_autolink = plain_text.trim() == url
+            && ["http://", "https://", "mailto:"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+            && !url.contains(|c: char| c.is_whitespace() || c == '<' || c == '>');
+        if is_autolink {
+            out.push('<');
+            out.push_str(&url);
+            out.push('>');
+        } else {
+            // Otherwise the link would become an image.
+            if out.ends_with('!') {
+                out.pop();
+                out.push_str("\\!");
+            }
+            out.push('[');
+            out.push_str(trimmed);
+            out.push_str("](");
+            push_destination(&url, element.attribute("title"), out);
+            out.push(')');
+        }
+        if text.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+
+    fn image(&self, element: &Element, out: &mut String) {
+        let Some(source) = image_source(element) else {
+            return;
+        };
+        let url = self.resolve(source);
+        let destination = self.image_destinations.get(&url).unwrap_or(&url);
+        if out.ends_with('!') {
+            out.pop();
+            out.push_str("\\!");
+        }
+        out.push_str("![");
+        let alt = collapse_whitespace(element.attribute("alt").unwrap_or_default());
+        for c in alt.trim().chars() {
+            if matches!(c, '\\' | '[' | ']') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push_str("](");
+        push_destination(destination, element.attribute("title"), out);
+        out.push(')');
+    }
+}
+
+/// Converts HTML, e.g. pasted rich text or a shared page, to markdown. Relative links and
+/// image sources are resolved against `base_url`.
+pub fn html_to_markdown(html: &str, base_url: Option<&str>) -> String {
+    HtmlToMarkdown::new(base_url).convert(&parse_html(html))
+}
+
+/// Escapes plain text, e.g. a page description, as a markdown paragraph.
+pub fn text_to_markdown(text: &str) -> String {
+    let mut inline = String::new();
+    push_text(text, &mut inline, InlineState::default());
+    let mut blocks = Vec::new();
+    push_paragraph(&mut inline, &mut blocks);
+    join_blocks(&blocks)
+}
+
+/// Lazy loaded images keep the real source in a data attribute.
+fn image_source(element: &Element) -> Option<&str> {
+    ["src", "data-src", "data-original"]
+        .iter()
+        .filter_map(|name| element.attribute(name))
+        .map(str::trim)
+        .find(|source| !source.is_empty())
+}
+
+fn join_blocks(blocks: &[Block]) -> String {
+    let mut markdown = String::new();
+    for block in blocks {
+        if !markdown.is_empty() {
+            markdown.push_str("\n\n");
+        }
+        markdown.push_str(&block.markdown);
+    }
+    markdown
+}
+
+fn push_paragraph(inline: &mut String, blocks: &mut Vec<Block>) {
+    // Lines are only split at hard breaks, so a line of a lone backslash is an empty one.
+    let mut lines: Vec<String> = inline
+        .split('\n')
+        .map(collapse_spaces)
+        .filter(|line| !line.is_empty() && line != "\\")
+        .collect();
+    inline.clear();
+    // A hard break at the end of a paragraph would be a literal backslash.
+    if let Some(last) = lines.last_mut() {
+        if last.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1 {
+            last.pop();
+            last.truncate(last.trim_end_matches(' ').len());
+        }
+    }
+    let mut paragraph = String::new();
+    for line in lines {
+        if !paragraph.is_empty() {
+            paragraph.push('\n');
+        }
+        escape_line_start(&line, &mut paragraph);
+    }
+    if !paragraph.trim().is_empty() {
+        blocks.push(Block {
+            markdown: paragraph,
+            list: false,
+        });
+    }
+}
+
+/// Escapes text that would start a block at the beginning of a line.
+fn escape_line_start(line: &str, out: &mut String) {
+    let bytes = line.as_bytes();
+    match bytes[0] {
+        b'#' | b'>' | b'-' | b'=' => out.push('\\'),
+        b'+' if matches!(bytes.get(1), None | Some(b' ')) => out.push('\\'),
+        b'0'..=b'9' => {
+            let digits = bytes.iter().take_while(|c| c.is_ascii_digit()).count();
+            if matches!(bytes.get(digits), Some(b'.' | b')'))
+                && matches!(bytes.get(digits + 1), None | Some(b' '))
+            {
+                out.push_str(&line[..digits]);
+                out.push('\\');
+                out.push_str(&line[digits..]);
+                return;
+            }
+        }
+        _ => {}
+    }
+    out.push_str(line);
+}
+
+fn push_text(text: &str, out: &mut String, state: InlineState) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if is_html_whitespace(c) {
+            if !out.ends_with(' ') {
+                out.push(' ');
+            }
+            continue;
+        }
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '~' => out.push('\\'),
+            '|' if state.table_cell => out.push('\\'),
+            // Only what could be read as an entity reference.
+            '&' if chars
+                .peek()
+                .is_some_and(|next| next.is_ascii_alphanumeric() || *next == '#') =>
+            {
+                out.push('\\')
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+}
+
+/// Collapses runs of spaces and trims them, leaving other whitespace as is.
+fn collapse_spaces(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.trim_matches(' ').chars() {
+        if c == ' ' && collapsed.ends_with(' ') {
+            continue;
+        }
+        collapsed.push(c);
+    }
+    collapsed
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if is_html_whitespace(c) {
+            if !collapsed.ends_with(' ') {
+                collapsed.push(' ');
+            }
+        } else {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
+/// The text of the element and its descendants, with `<br>` as a newline.
+fn text_content(element: &Element) -> String {
+    fn push(element: &Element, text: &mut String) {
+        for child in &element.children {
+            match child {
+                Node::Text(content) => text.push_str(content),
+                Node::Element(child) if child.name == "br" => text.push('\n'),
+                Node::Element(child) if SKIPPED_ELEMENTS.contains(&child.name.as_str()) => {}
+                Node::Element(child) => push(child, text),
+            }
+        }
+    }
+    let mut text = String::new();
+    push(element, &mut text);
+    text
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+fn code_span(code: &str) -> String {
+    let fence = "`".repeat(longest_backtick_run(code) + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{fence} {code} {fence}")
+    } else {
+        format!("{fence}{code}{fence}")
+    }
+}
+
+fn code_block(element: &Element) -> String {
+    let code = text_content(element);
+    // The newline right after `<pre>` isn't part of the content.
+    let code = code
+        .strip_prefix("\r\n")
+        .or_else(|| code.strip_prefix('\n'))
+        .unwrap_or(&code);
+    let code = code.trim_end_matches(['\n', '\r']);
+    let language = std::iter::once(element)
+        .chain(element.elements().filter(|child| child.name == "code"))
+        .filter_map(|element| element.attribute("class"))
+        .flat_map(str::split_ascii_whitespace)
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_backtick_run(code).max(2) + 1);
+    format!("{fence}{language}\n{code}\n{fence}")
+}
+
+fn cell_alignment(cell: &Element) -> &'static str {
+    let style = cell
+        .attribute("style")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let style_alignment = style.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        (property.trim() == "text-align").then(|| value.trim().to_string())
+    });
+    let alignment = style_alignment
+        .or_else(|| cell.attribute("align").map(str::to_ascii_lowercase))
+        .unwrap_or_default();
+    match alignment.as_str() {
+        "left" => ":---",
+        "center" => ":---:",
+        "right" => "---:",
+        _ => "---",
+    }
+}
+
+fn prefix_lines(text: &str, prefix: &str, empty_line_prefix: &str) -> String {
+    let mut prefixed = String::with_capacity(text.len());
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            prefixed.push('\n');
+        }
+        if line.is_empty() {
+            prefixed.push_str(empty_line_prefix);
+        } else {
+            prefixed.push_str(prefix);
+            prefixed.push_str(line);
+        }
+    }
+    prefixed
+}
+
+fn push_destination(url: &str, title: Option<&str>, out: &mut String) {
+    if url.contains(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '<' | '>')) {
+        out.push('<');
+        out.push_str(&url.replace('<', "%3C").replace('>', "%3E"));
+        out.push('>');
+    } else {
+        out.push_str(url);
+    }
+    let title = title.map(collapse_whitespace);
+    if let Some(title) = title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        out.push_str(" \"");
+        for c in title.chars() {
+            if matches!(c, '"' | '\\') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('"');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_URL: Option<&str> = Some("https://example.com/a/page.html");
+
+    fn assert_converts(html: &str, markdown: &str) {
+        assert_eq!(html_to_markdown(html, BASE_URL), markdown, "{html}");
+    }
+
+    #[test]
+    fn headings() {
+        assert_converts(
+            "<h1>Title</h1><h2>Sub <em>title</em></h2><h6>Six</h6>",
+            "# Title\n\n## Sub *title*\n\n###### Six",
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_converts(
+            "<ul><li>One</li><li>Two<ul><li>Nested</li></ul></li></ul><ol start=\"3\"><li>Three</li><li>Four</li></ol>",
+            "- One\n- Two\n  - Nested\n\n3. Three\n4. Four",
+        );
+        assert_converts(
+            "<ul><li><input type=\"checkbox\" checked> Done</li><li><input type=\"checkbox\"> Todo</li></ul>",
+            "- [x] Done\n- [ ] Todo",
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_converts(
+            "<table><thead><tr><th>Name</th><th align=\"right\">Count</th><th style=\"text-align: center\">C</th></tr></thead><tbody><tr><td>a | b</td><td>1</td><td></td></tr></tbody></table>",
+            "| Name | Count | C |\n| --- | ---: | :---: |\n| a \\| b | 1 | |",
+        );
+    }
+
+    #[test]
+    fn code() {
+        assert_converts(
+            "<pre><code class=\"language-rust\">fn main() {\n    println!(\"```\");\n}\n</code></pre><p>Inline <code>a `b` c</code></p>",
+            "````rust\nfn main() {\n    println!(\"```\");\n}\n````\n\nInline ``a `b` c``",
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_converts(
+            "<p><a href=\"/docs?q=1\" title=\"The docs\">Docs</a> <a href=\"https://example.com/\">https://example.com/</a> <img src=\"img.png\" alt=\"An [image]\"></p>",
+            "[Docs](https://example.com/docs?q=1 \"The docs\") <https://example.com/> ![An \\[image\\]](https://example.com/a/img.png)",
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_converts(
+            "<p>*not em* _x_ [a](b) `c` &lt;tag&gt; a\\b</p><p>1. no</p><p># no</p><p>2) no</p>",
+            "\\*not em\\* \\_x\\_ \\[a\\](b) \\`c\\` \\<tag> a\\\\b\n\n1\\. no\n\n\\# no\n\n2\\) no",
+        );
+        assert_converts(
+            "<p>Line<br>break</p><blockquote><p>Quote</p></blockquote><hr>",
+            "Line\\\nbreak\n\n> Quote\n\n---",
+        );
+    }
+}
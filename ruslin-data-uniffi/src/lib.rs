@@ -8,13 +8,52 @@ use log4rs::{
 };
 use ruslin_data::{
     sync::{SyncConfig, SyncError},
-    DatabaseError, Folder, Note, Resource, RuslinData, SearchBodyOption, UpdateSource,
+    AbbrNote, DatabaseError, DateTimeTimestamp, Folder, ModelType, Note, Resource, RuslinData,
+    SearchBodyOption, UpdateSource,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
 };
-use std::path::Path;
 use tokio::runtime::Runtime;
+mod clipper;
+mod diagram;
+mod dom;
+mod download;
+mod duplicate;
+mod editor;
 mod ffi;
+mod folder_tree;
 mod html;
-use ffi::{FFIAbbrNote, FFIFolder, FFINote, FFIResource, FFISearchNote, FFIStatus, FFISyncInfo};
+mod html_to_markdown;
+mod link;
+mod math;
+mod note_list;
+mod note_meta;
+mod outline;
+mod pin;
+mod plain_text;
+mod recent;
+mod statistics;
+mod template;
+#[cfg(test)]
+mod test_util;
+mod utf16;
+use clipper::ClipMode;
+use editor::{FFITextEdit, FormatCommand};
+use ffi::{
+    FFIAbbrNote, FFIError, FFIFolder, FFIFolderNode, FFINote, FFINoteListOptions, FFINoteSummary,
+    FFIResource, FFISearchNote, FFIStatus, FFISyncInfo, NoteSortKey, SortDirection,
+};
+use html_to_markdown::HtmlToMarkdown;
+use math::MaskedText;
+use note_list::NoteListItem;
+use note_meta::{NoteMeta, NoteMetaCache};
+use outline::FFIOutlineHeading;
+use plain_text::FFIPlainTextOptions;
+use recent::RecentNotes;
+use statistics::FFINoteStatistics;
+use template::{FFITemplate, TemplateContext};
 
 uniffi::include_scaffolding!("ruslin");
 
@@ -70,8 +109,59 @@ fn init_log(log_text_file: &str) -> log4rs::Handle {
     log4rs::init_config(config).unwrap()
 }
 
+fn now_timestamp() -> DateTimeTimestamp {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    DateTimeTimestamp::from_timestamp_millis(millis)
+}
+
+/// Returns an error if `folder_id` can't be placed under `parent_id`, i.e. the parent
+/// doesn't exist or is the folder itself or one of its descendants.
+fn check_folder_parent(
+    folders: &[Folder],
+    folder_id: &str,
+    parent_id: Option<&str>,
+) -> Result<(), FFIError> {
+    let Some(parent_id) = parent_id.filter(|id| !id.is_empty()) else {
+        return Ok(());
+    };
+    let parents: HashMap<&str, Option<&str>> = folders
+        .iter()
+        .map(|f| (f.id.as_str(), f.parent_id.as_deref()))
+        .collect();
+    if !parents.contains_key(parent_id) {
+        return Err(FFIError::InvalidArgument(format!(
+            "parent folder {parent_id} not found"
+        )));
+    }
+    let mut ancestor = Some(parent_id);
+    let mut depth = 0;
+    while let Some(id) = ancestor {
+        if id == folder_id {
+            return Err(FFIError::InvalidArgument(format!(
+                "folder {folder_id} can't be moved into itself or its descendant"
+            )));
+        }
+        // Guard against cycles that already exist in synced data.
+        depth += 1;
+        if depth > parents.len() {
+            break;
+        }
+        ancestor = parents.get(id).copied().flatten();
+    }
+    Ok(())
+}
+
+/// Set as the `source_application` of notes created from outside the app, like Joplin does.
+const SOURCE_APPLICATION: &str = "org.dianqk.ruslin";
+
 pub struct RuslinAndroidData {
     data: RuslinData,
+    resource_dir: PathBuf,
+    recent_notes: RecentNotes,
+    note_meta: NoteMetaCache,
     rt: Runtime,
     _log_handle: log4rs::Handle,
 }
@@ -90,6 +180,9 @@ impl RuslinAndroidData {
         let data = RuslinData::new(Path::new(&data_dir), Path::new(&resource_dir))?;
         Ok(Self {
             data,
+            resource_dir: PathBuf::from(resource_dir),
+            recent_notes: RecentNotes::new(Path::new(&data_dir)),
+            note_meta: NoteMetaCache::default(),
             rt,
             _log_handle: log_handle,
         })
@@ -129,6 +222,8 @@ impl RuslinAndroidData {
 
     pub fn synchronize(&self, from_scratch: bool) -> Result<FFISyncInfo, SyncError> {
         let result = self.rt.block_on(self.data.synchronize(from_scratch));
+        // Even a failed sync may have written some notes.
+        self.note_meta.clear();
         if let Err(e) = &result {
             log::error!("sync error: {e}");
         }
@@ -139,10 +234,33 @@ impl RuslinAndroidData {
         Folder::new(title, parent_id).into()
     }
 
-    pub fn replace_folder(&self, folder: FFIFolder) -> Result<(), DatabaseError> {
+    pub fn replace_folder(&self, folder: FFIFolder) -> Result<(), FFIError> {
+        let folders = self.data.db.load_folders()?;
+        check_folder_parent(&folders, &folder.id, folder.parent_id.as_deref())?;
+        self.data
+            .db
+            .replace_folder(&folder.into(), ruslin_data::UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    pub fn move_folder(&self, id: String, new_parent_id: Option<String>) -> Result<(), FFIError> {
+        // An empty parent id means the root, like in synced data.
+        let new_parent_id = new_parent_id.filter(|id| !id.is_empty());
+        let folders = self.data.db.load_folders()?;
+        check_folder_parent(&folders, &id, new_parent_id.as_deref())?;
+        let Some(mut folder) = folders.into_iter().find(|f| f.id == id) else {
+            return Err(FFIError::InvalidArgument(format!("folder {id} not found")));
+        };
+        if folder.parent_id.as_deref().filter(|id| !id.is_empty()) == new_parent_id.as_deref() {
+            return Ok(());
+        }
+        folder.parent_id = new_parent_id;
+        // Moving is not a user edit, so `user_updated_time` is kept as is (same as Joplin).
+        folder.updated_time = now_timestamp();
         self.data
             .db
-            .replace_folder(&folder.into(), ruslin_data::UpdateSource::LocalEdit)
+            .replace_folder(&folder, UpdateSource::LocalEdit)?;
+        Ok(())
     }
 
     pub fn load_folders(&self) -> Result<Vec<FFIFolder>, DatabaseError> {
@@ -154,6 +272,13 @@ impl RuslinAndroidData {
         Ok(folders)
     }
 
+    pub fn load_folder_tree(&self) -> Result<Vec<FFIFolderNode>, DatabaseError> {
+        let folders = self.data.db.load_folders()?;
+        let notes = self.data.db.load_abbr_notes(None)?;
+        let metas = self.load_note_metas(&notes)?;
+        Ok(folder_tree::build_folder_tree(folders, &notes, &metas))
+    }
+
     pub fn delete_folder(&self, id: String) -> Result<(), DatabaseError> {
         self.data.db.delete_folder(&id, UpdateSource::LocalEdit)
     }
@@ -170,27 +295,536 @@ impl RuslinAndroidData {
         Ok(notes)
     }
 
+    /// Filters and sorts the abbreviated rows of the folder, with the other fields from the
+    /// meta cache, and only loads the full notes of the requested page. Every page still
+    /// reads all abbreviated rows of the folder, as the database can't sort or page on
+    /// these fields, and the first listing loads every note once to fill the cache.
+    pub fn load_note_list(
+        &self,
+        parent_id: Option<String>,
+        options: FFINoteListOptions,
+    ) -> Result<Vec<FFINoteSummary>, DatabaseError> {
+        let notes = self.data.db.load_abbr_notes(parent_id.as_deref())?;
+        let metas = if note_list::needs_note_meta(&options) {
+            self.load_note_metas(&notes)?
+        } else {
+            vec![NoteMeta::default(); notes.len()]
+        };
+        let items = notes
+            .into_iter()
+            .zip(metas)
+            .map(|(abbr, meta)| NoteListItem { abbr, meta })
+            .collect();
+        self.note_summaries(items, &options)
+    }
+
+    /// Pinned notes of all folders, listed with the options. Like `load_note_list`, pinned
+    /// notes are found with the meta cache and only the requested page is loaded.
+    pub fn load_pinned_notes(
+        &self,
+        options: FFINoteListOptions,
+    ) -> Result<Vec<FFINoteSummary>, DatabaseError> {
+        let notes = self.data.db.load_abbr_notes(None)?;
+        let metas = self.load_note_metas(&notes)?;
+        let items = notes
+            .into_iter()
+            .zip(metas)
+            .filter(|(_, meta)| meta.is_pinned && !meta.is_conflict)
+            .map(|(abbr, meta)| NoteListItem { abbr, meta })
+            .collect();
+        self.note_summaries(items, &options)
+    }
+
+    fn note_summaries(
+        &self,
+        items: Vec<NoteListItem>,
+        options: &FFINoteListOptions,
+    ) -> Result<Vec<FFINoteSummary>, DatabaseError> {
+        let excerpt_length = options.excerpt_length.max(0) as usize;
+        note_list::list_notes(items, options)
+            .into_iter()
+            .map(|item| {
+                let note = self.data.db.load_note(&item.abbr.id)?;
+                Ok(note_list::note_summary(&self.data, note, excerpt_length))
+            })
+            .collect()
+    }
+
+    fn load_note_metas(&self, notes: &[AbbrNote]) -> Result<Vec<NoteMeta>, DatabaseError> {
+        self.note_meta.get(notes, |id| self.data.db.load_note(id))
+    }
+
+    /// Loads the full notes, the database only has a bulk query for `AbbrNote`.
+    fn load_notes(&self, parent_id: Option<&str>) -> Result<Vec<Note>, DatabaseError> {
+        self.data
+            .db
+            .load_abbr_notes(parent_id)?
+            .into_iter()
+            .map(|note| self.data.db.load_note(&note.id))
+            .collect()
+    }
+
     pub fn new_note(&self, parent_id: Option<String>, title: String, body: String) -> FFINote {
         Note::new(parent_id, title, body).into()
     }
 
+    /// Lists the notes of the templates folders. Tagging a note doesn't make it a template.
+    pub fn list_templates(&self) -> Result<Vec<FFITemplate>, DatabaseError> {
+        let folders = self.data.db.load_folders()?;
+        let mut templates = Vec::new();
+        for folder_id in template::template_folder_ids(&folders) {
+            for note in self.load_notes(Some(folder_id))? {
+                templates.push(FFITemplate {
+                    variables: template::variables(&format!("{}\n{}", note.title, note.body)),
+                    id: note.id,
+                    title: note.title,
+                });
+            }
+        }
+        templates.sort_by_key(|template| template.title.to_lowercase());
+        Ok(templates)
+    }
+
+    /// Like `new_note`, the note isn't saved. Its title is `variables["title"]` if given, or
+    /// else the expanded title of the template.
+    pub fn new_note_from_template(
+        &self,
+        template_id: String,
+        parent_id: Option<String>,
+        variables: HashMap<String, String>,
+    ) -> Result<FFINote, DatabaseError> {
+        let template = self.data.db.load_note(&template_id)?;
+        let folder = match parent_id.as_deref().filter(|id| !id.is_empty()) {
+            Some(parent_id) => self
+                .data
+                .db
+                .load_folders()?
+                .into_iter()
+                .find(|folder| folder.id == parent_id)
+                .map(|folder| folder.title)
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+        let mut context = TemplateContext {
+            now: chrono::Local::now(),
+            title: "",
+            folder: &folder,
+            variables: &variables,
+        };
+        let title = match variables.get("title") {
+            Some(title) => title.clone(),
+            None => template::expand(&template.title, &context),
+        };
+        context.title = &title;
+        let body = template::expand(&template.body, &context);
+        let mut note = Note::new(parent_id, title.clone(), body);
+        note.is_todo = template.is_todo;
+        Ok(note.into())
+    }
+
     pub fn load_note(&self, id: String) -> Result<FFINote, DatabaseError> {
         Ok(self.data.db.load_note(&id)?.into())
     }
 
     pub fn replace_note(&self, note: FFINote) -> Result<(), DatabaseError> {
-        self.data
-            .db
-            .replace_note(&note.into(), UpdateSource::LocalEdit)
+        self.save_note(&note.into())
+    }
+
+    /// Saves the note. Every note this crate writes goes through here, so that the cached
+    /// meta of the note is dropped even if its `user_updated_time`, which keys the cache,
+    /// didn't change, e.g. when pinning.
+    fn save_note(&self, note: &Note) -> Result<(), DatabaseError> {
+        let result = self.data.db.replace_note(note, UpdateSource::LocalEdit);
+        // After the write, so that a list loading the note meanwhile can't cache the old meta.
+        self.note_meta.invalidate(&[&note.id]);
+        result
     }
 
     pub fn delete_note(&self, id: String) -> Result<(), DatabaseError> {
-        self.data.db.delete_note(&id, UpdateSource::LocalEdit)
+        self.note_meta.invalidate(&[&id]);
+        self.data.db.delete_note(&id, UpdateSource::LocalEdit)?;
+        self.recent_notes.remove(&[&id]);
+        Ok(())
     }
 
     pub fn delete_notes(&self, ids: Vec<String>) -> Result<(), DatabaseError> {
         let ids: Vec<_> = ids.iter().map(|s| s.as_str()).collect();
-        self.data.db.delete_notes(&ids)
+        self.note_meta.invalidate(&ids);
+        self.data.db.delete_notes(&ids)?;
+        self.recent_notes.remove(&ids);
+        Ok(())
+    }
+
+    /// Records that the note was opened, for the recent notes. The history is local to the
+    /// device and isn't synced.
+    pub fn mark_note_opened(&self, id: String) {
+        let opened_time = now_timestamp().timestamp_millis();
+        self.recent_notes.mark_opened(&id, opened_time);
+    }
+
+    /// The most recently opened notes first. Notes deleted since, including by a sync, are
+    /// skipped and dropped from the history. Only the recorded notes are loaded, up to
+    /// `limit` of them.
+    pub fn load_recent_notes(&self, limit: i32) -> Result<Vec<FFIAbbrNote>, DatabaseError> {
+        let limit = limit.max(0) as usize;
+        let entries = self.recent_notes.entries();
+        let mut notes: Vec<FFIAbbrNote> = Vec::with_capacity(limit.min(entries.len()));
+        let mut deleted_ids: Vec<&str> = Vec::new();
+        for entry in &entries {
+            if notes.len() == limit {
+                break;
+            }
+            // ruslin-data has no error of its own for a missing note.
+            let Ok(note) = self.data.db.load_note(&entry.id) else {
+                deleted_ids.push(&entry.id);
+                continue;
+            };
+            notes.push(FFIAbbrNote {
+                id: note.id,
+                parent_id: note.parent_id,
+                title: note.title,
+                user_created_time: note.user_created_time.timestamp_millis(),
+                user_updated_time: note.user_updated_time.timestamp_millis(),
+            });
+        }
+        if !deleted_ids.is_empty() {
+            self.recent_notes.remove(&deleted_ids);
+        }
+        Ok(notes)
+    }
+
+    pub fn clear_recent_notes(&self) {
+        self.recent_notes.clear();
+    }
+
+    /// Moves all notes to `target_folder_id`. Every note is loaded before the first write,
+    /// and already moved notes are restored if a later write fails.
+    pub fn move_notes(&self, ids: Vec<String>, target_folder_id: String) -> Result<(), FFIError> {
+        let folders = self.data.db.load_folders()?;
+        if !folders.iter().any(|f| f.id == target_folder_id) {
+            return Err(FFIError::InvalidArgument(format!(
+                "folder {target_folder_id} not found"
+            )));
+        }
+        let notes = ids
+            .iter()
+            .map(|id| self.data.db.load_note(id))
+            .collect::<Result<Vec<Note>, DatabaseError>>()?;
+        let updated_time = now_timestamp();
+        let mut updates: Vec<(Note, Note)> = Vec::with_capacity(notes.len());
+        for note in notes {
+            if note.parent_id.as_deref() == Some(target_folder_id.as_str()) {
+                continue;
+            }
+            let mut moved_note = note.clone();
+            moved_note.parent_id = Some(target_folder_id.clone());
+            moved_note.is_conflict = false;
+            moved_note.conflict_original_id = None;
+            moved_note.updated_time = updated_time;
+            updates.push((note, moved_note));
+        }
+        self.replace_notes_or_restore(updates)?;
+        Ok(())
+    }
+
+    /// Pins or unpins the notes. Like moving, every note is loaded before the first write and
+    /// already updated notes are restored if a later write fails.
+    pub fn set_pinned(&self, ids: Vec<String>, pinned: bool) -> Result<(), FFIError> {
+        let notes = ids
+            .iter()
+            .map(|id| self.data.db.load_note(id))
+            .collect::<Result<Vec<Note>, DatabaseError>>()?;
+        let updated_time = now_timestamp();
+        let mut updates: Vec<(Note, Note)> = Vec::with_capacity(notes.len());
+        for note in notes {
+            if pin::is_pinned(&note.application_data) == pinned {
+                continue;
+            }
+            let Some(application_data) = pin::set_pinned(&note.application_data, pinned) else {
+                return Err(FFIError::InvalidArgument(format!(
+                    "application data of note {} isn't a JSON object",
+                    note.id
+                )));
+            };
+            let mut pinned_note = note.clone();
+            pinned_note.application_data = application_data;
+            // Not a user edit, the same as moving.
+            pinned_note.updated_time = updated_time;
+            updates.push((note, pinned_note));
+        }
+        self.replace_notes_or_restore(updates)?;
+        Ok(())
+    }
+
+    /// Completes or reopens the to-dos, e.g. from a checkbox in the note list. Notes that
+    /// aren't to-dos are rejected before anything is written.
+    pub fn set_todo_completed(&self, ids: Vec<String>, completed: bool) -> Result<(), FFIError> {
+        let notes = ids
+            .iter()
+            .map(|id| self.data.db.load_note(id))
+            .collect::<Result<Vec<Note>, DatabaseError>>()?;
+        if let Some(note) = notes.iter().find(|note| !note.is_todo) {
+            return Err(FFIError::InvalidArgument(format!(
+                "note {} isn't a to-do",
+                note.id
+            )));
+        }
+        let updated_time = now_timestamp();
+        let mut updates: Vec<(Note, Note)> = Vec::with_capacity(notes.len());
+        for note in notes {
+            if note.todo_completed == completed {
+                continue;
+            }
+            let mut updated_note = note.clone();
+            updated_note.todo_completed = completed;
+            updated_note.updated_time = updated_time;
+            updated_note.user_updated_time = updated_time;
+            updates.push((note, updated_note));
+        }
+        self.replace_notes_or_restore(updates)?;
+        Ok(())
+    }
+
+    /// Saves the updated notes of `(original, updated)` pairs. If a write fails, the notes
+    /// saved so far are restored to the originals.
+    fn replace_notes_or_restore(&self, updates: Vec<(Note, Note)>) -> Result<(), DatabaseError> {
+        let mut replaced_notes: Vec<Note> = Vec::with_capacity(updates.len());
+        for (note, updated_note) in updates {
+            if let Err(e) = self.save_note(&updated_note) {
+                log::error!("update note {} error: {e}", note.id);
+                for original_note in replaced_notes {
+                    if let Err(e) = self.save_note(&original_note) {
+                        log::error!("restore note {} error: {e}", original_note.id);
+                    }
+                }
+                return Err(e);
+            }
+            replaced_notes.push(note);
+        }
+        Ok(())
+    }
+
+    /// Creates a copy of the note in `target_folder_id`, or next to the note. With
+    /// `copy_resources`, the linked resources are copied too and the copy links to them.
+    pub fn duplicate_note(
+        &self,
+        id: String,
+        target_folder_id: Option<String>,
+        copy_resources: bool,
+    ) -> Result<FFINote, FFIError> {
+        let note = self.data.db.load_note(&id)?;
+        let parent_id = match target_folder_id {
+            Some(target_folder_id) => {
+                let folders = self.data.db.load_folders()?;
+                if !folders.iter().any(|f| f.id == target_folder_id) {
+                    return Err(FFIError::InvalidArgument(format!(
+                        "folder {target_folder_id} not found"
+                    )));
+                }
+                Some(target_folder_id)
+            }
+            None => note.parent_id.clone(),
+        };
+        let title = format!("{} (copy)", note.title);
+        let mut resource_copies = HashMap::new();
+        let copy = self.copy_note(
+            &note,
+            parent_id,
+            title,
+            copy_resources.then_some(&mut resource_copies),
+        )?;
+        Ok(copy.into())
+    }
+
+    /// Copies the folder with its subfolders and notes next to it. Resources linked from
+    /// several notes are copied once.
+    pub fn duplicate_folder(
+        &self,
+        id: String,
+        copy_resources: bool,
+    ) -> Result<FFIFolder, FFIError> {
+        let folders = self.data.db.load_folders()?;
+        let Some(folder) = folders.iter().find(|f| f.id == id) else {
+            return Err(FFIError::InvalidArgument(format!("folder {id} not found")));
+        };
+        let title = format!("{} (copy)", folder.title);
+        let mut state = FolderCopy {
+            resource_copies: copy_resources.then(HashMap::new),
+            ..Default::default()
+        };
+        match self.copy_folder(
+            &folders,
+            folder,
+            folder.parent_id.clone(),
+            title,
+            &mut state,
+            &mut vec![folder.id.as_str()],
+        ) {
+            Ok(copy) => Ok(copy.into()),
+            Err(e) => {
+                log::error!("duplicate folder {id} error: {e}");
+                self.delete_folder_copy(&state);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn copy_folder<'a>(
+        &self,
+        folders: &'a [Folder],
+        folder: &Folder,
+        parent_id: Option<String>,
+        title: String,
+        state: &mut FolderCopy,
+        ancestors: &mut Vec<&'a str>,
+    ) -> Result<Folder, DatabaseError> {
+        let mut copy = Folder::new(title, parent_id);
+        copy.icon = folder.icon.clone();
+        self.data
+            .db
+            .replace_folder(&copy, UpdateSource::LocalEdit)?;
+        state.folder_ids.push(copy.id.clone());
+        for note in self.load_notes(Some(&folder.id))? {
+            if note.is_conflict {
+                continue;
+            }
+            let title = note.title.clone();
+            let note_copy = self.copy_note(
+                &note,
+                Some(copy.id.clone()),
+                title,
+                state.resource_copies.as_mut(),
+            )?;
+            state.note_ids.push(note_copy.id);
+        }
+        for child in folders {
+            // Folders caught in a parent cycle are only copied once.
+            if child.parent_id.as_deref() != Some(folder.id.as_str())
+                || ancestors.contains(&child.id.as_str())
+            {
+                continue;
+            }
+            ancestors.push(&child.id);
+            self.copy_folder(
+                folders,
+                child,
+                Some(copy.id.clone()),
+                child.title.clone(),
+                state,
+                ancestors,
+            )?;
+            ancestors.pop();
+        }
+        Ok(copy)
+    }
+
+    /// Deletes the folders and notes of a copy that failed midway. The copied resources are
+    /// kept, like the resources a note no longer links to.
+    fn delete_folder_copy(&self, state: &FolderCopy) {
+        let note_ids: Vec<&str> = state.note_ids.iter().map(String::as_str).collect();
+        if let Err(e) = self.data.db.delete_notes(&note_ids) {
+            log::error!("delete copied notes error: {e}");
+        }
+        // Children were created after their parents.
+        for folder_id in state.folder_ids.iter().rev() {
+            if let Err(e) = self
+                .data
+                .db
+                .delete_folder(folder_id, UpdateSource::LocalEdit)
+            {
+                log::error!("delete copied folder {folder_id} error: {e}");
+            }
+        }
+    }
+
+    fn copy_note(
+        &self,
+        note: &Note,
+        parent_id: Option<String>,
+        title: String,
+        resource_copies: Option<&mut HashMap<String, String>>,
+    ) -> Result<Note, DatabaseError> {
+        let body = match resource_copies {
+            Some(resource_copies) => {
+                self.copy_linked_resources(&note.body, resource_copies)?;
+                duplicate::replace_linked_ids(&note.body, resource_copies)
+            }
+            None => note.body.clone(),
+        };
+        let copy = duplicate::copy_note(note, parent_id, title, body);
+        self.save_note(&copy)?;
+        Ok(copy)
+    }
+
+    /// Copies the resources linked in the body that haven't been copied yet, adding them to
+    /// `resource_copies`. Resources without a local file, e.g. not downloaded by the sync
+    /// yet, and encrypted ones keep linking to the original.
+    fn copy_linked_resources(
+        &self,
+        body: &str,
+        resource_copies: &mut HashMap<String, String>,
+    ) -> Result<(), DatabaseError> {
+        for id in duplicate::linked_ids(body) {
+            if resource_copies.contains_key(id) {
+                continue;
+            }
+            let is_resource = self
+                .data
+                .db
+                .load_sync_item(id)
+                .is_ok_and(|item| matches!(item.item_type, ModelType::Resource));
+            if !is_resource {
+                continue;
+            }
+            let resource = self.data.db.load_resource(id)?;
+            if resource.encryption_applied || resource.encryption_blob_encrypted {
+                continue;
+            }
+            let mut copy = Resource::new(
+                resource.title.clone(),
+                resource.mime.clone(),
+                resource.file_extension.clone(),
+                resource.size,
+            );
+            copy.filename = resource.filename.clone();
+            let source = self.resource_path(&resource);
+            if let Err(e) = std::fs::copy(&source, self.resource_path(&copy)) {
+                log::warn!("failed to copy {}: {e}", source.display());
+                continue;
+            }
+            self.data
+                .db
+                .replace_resource(&copy, UpdateSource::LocalEdit)?;
+            resource_copies.insert(id.to_string(), copy.id);
+        }
+        Ok(())
+    }
+
+    fn resource_path(&self, resource: &Resource) -> PathBuf {
+        // Resources without an extension are stored under their id alone.
+        if resource.file_extension.is_empty() {
+            self.resource_dir.join(&resource.id)
+        } else {
+            self.resource_dir
+                .join(format!("{}.{}", resource.id, resource.file_extension))
+        }
+    }
+
+    /// Checks or unchecks the task list item whose marker starts at the UTF-16 `offset` of
+    /// the note body, as annotated by `data-task-offset` in the preview.
+    pub fn toggle_task(&self, note_id: String, offset: i32) -> Result<FFINote, FFIError> {
+        let mut note = self.data.db.load_note(&note_id)?;
+        let Some(body) = editor::toggle_task_marker(&note.body, offset.max(0) as usize) else {
+            return Err(FFIError::InvalidArgument(format!(
+                "no task list marker at offset {offset}"
+            )));
+        };
+        note.body = body;
+        let updated_time = now_timestamp();
+        note.updated_time = updated_time;
+        note.user_updated_time = updated_time;
+        self.save_note(&note)?;
+        Ok(note.into())
     }
 
     pub fn conflict_note_exists(&self) -> Result<bool, DatabaseError> {
@@ -263,7 +897,8 @@ impl RuslinAndroidData {
                 }
             })
             .collect();
-        Ok(notes)
+        let recent = self.recent_notes.entries();
+        Ok(recent::rank_by_recency(notes, |note| &note.id, &recent))
     }
 
     pub fn create_resource(
@@ -287,9 +922,221 @@ impl RuslinAndroidData {
         Ok(resource.into())
     }
 
-    pub fn parse_markdown_to_preview_html(&self, text: String) -> String {
-        html::parse_markdown_to_preview_html(&self.data, text)
+    /// Like `html_to_markdown`, but downloads the images into resources. Images that can't be
+    /// downloaded keep their URLs.
+    pub fn html_to_markdown_with_resources(
+        &self,
+        html: String,
+        base_url: Option<String>,
+    ) -> Result<String, DatabaseError> {
+        let document = dom::parse_html(&html);
+        let mut converter = HtmlToMarkdown::new(base_url.as_deref());
+        let urls = converter.image_urls(&document);
+        converter.image_destinations = self.download_images(&urls)?;
+        Ok(converter.convert(&document))
+    }
+
+    /// Fetches the page and saves it as a note in the folder. Images of the clipped content are
+    /// saved as resources. Bookmarks only read the head of the page, and can link to any file.
+    pub fn clip_url(
+        &self,
+        url: String,
+        folder_id: Option<String>,
+        mode: ClipMode,
+    ) -> Result<FFINote, FFIError> {
+        let client = download::client().map_err(|e| FFIError::Network(e.to_string()))?;
+        let page = self
+            .rt
+            .block_on(async {
+                match mode {
+                    ClipMode::Bookmark => {
+                        download::download_head(&client, &url, download::MAX_BOOKMARK_SIZE).await
+                    }
+                    _ => download::download(&client, &url, download::MAX_PAGE_SIZE).await,
+                }
+            })
+            .map_err(|e| FFIError::Network(e.to_string()))?;
+        let is_html = matches!(
+            page.mime.as_deref(),
+            None | Some("text/html" | "application/xhtml+xml")
+        );
+        // Other files can still be bookmarked, titled with their URL.
+        if !is_html && mode != ClipMode::Bookmark {
+            return Err(FFIError::InvalidArgument(format!("{url} isn't a web page")));
+        }
+        let html = if is_html {
+            clipper::decode_page(&page)
+        } else {
+            String::new()
+        };
+        let mut document = dom::parse_html(&html);
+        let metadata = clipper::metadata(&document);
+        let base_url = metadata
+            .base_url
+            .as_deref()
+            .and_then(|base_url| url::Url::parse(&page.url).ok()?.join(base_url).ok())
+            .map_or_else(|| page.url.clone(), String::from);
+        let mut converter = HtmlToMarkdown::new(Some(&base_url));
+
+        let body = match mode {
+            ClipMode::Bookmark => {
+                let mut body = format!("<{}>", page.url);
+                if let Some(description) = &metadata.description {
+                    body.push_str("\n\n");
+                    body.push_str(&html_to_markdown::text_to_markdown(description));
+                }
+                body
+            }
+            ClipMode::FullPage | ClipMode::Simplified => {
+                let content = match mode {
+                    ClipMode::Simplified => clipper::main_content(&mut document),
+                    _ => vec![clipper::body(&document)],
+                };
+                let mut urls: Vec<String> = Vec::new();
+                for element in &content {
+                    for image_url in converter.image_urls(element) {
+                        if !urls.contains(&image_url) {
+                            urls.push(image_url);
+                        }
+                    }
+                }
+                converter.image_destinations = self.download_images(&urls)?;
+                converter.convert_elements(&content)
+            }
+        };
+
+        let title = metadata.title.unwrap_or_else(|| page.url.clone());
+        let mut note = Note::new(folder_id, title, body);
+        note.source_url = page.url;
+        note.author = metadata.author.unwrap_or_default();
+        note.source_application = SOURCE_APPLICATION.to_string();
+        self.save_note(&note)?;
+        Ok(note.into())
+    }
+
+    /// Downloads the images into resources, returning the `:/id` link for each saved URL.
+    fn download_images(&self, urls: &[String]) -> Result<HashMap<String, String>, DatabaseError> {
+        let mut destinations = HashMap::new();
+        let urls: Vec<&String> = urls
+            .iter()
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .collect();
+        if urls.is_empty() {
+            return Ok(destinations);
+        }
+        let client = match download::client() {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("failed to create the http client: {e}");
+                return Ok(destinations);
+            }
+        };
+        for url in urls {
+            let image =
+                match self
+                    .rt
+                    .block_on(download::download(&client, url, download::MAX_IMAGE_SIZE))
+                {
+                    Ok(image) => image,
+                    Err(e) => {
+                        log::warn!("failed to download {url}: {e}");
+                        continue;
+                    }
+                };
+            let Some((mime, file_extension)) = download::image_type(&image) else {
+                log::warn!("{url} isn't an image");
+                continue;
+            };
+            let title = download::file_name(&image.url)
+                .unwrap_or_else(|| format!("image.{file_extension}"));
+            let resource = Resource::new(
+                title,
+                mime.to_string(),
+                file_extension.to_string(),
+                image.bytes.len() as i32,
+            );
+            let path = self.resource_path(&resource);
+            if let Err(e) = std::fs::write(&path, &image.bytes) {
+                log::warn!("failed to write {}: {e}", path.display());
+                continue;
+            }
+            self.data
+                .db
+                .replace_resource(&resource, UpdateSource::LocalEdit)?;
+            destinations.insert(url.clone(), format!(":/{}", resource.id));
+        }
+        Ok(destinations)
+    }
+
+    pub fn markdown_to_plain_text(&self, text: String, options: FFIPlainTextOptions) -> String {
+        plain_text::markdown_to_plain_text(&self.data, &text, &options)
     }
+
+    pub fn parse_markdown_to_preview_html(
+        &self,
+        text: String,
+        source_positions: bool,
+        table_of_contents: bool,
+    ) -> String {
+        let options = html::PreviewOptions {
+            source_positions,
+            table_of_contents,
+        };
+        html::parse_markdown_to_preview_html(&self.data, text, options)
+    }
+}
+
+pub fn extract_outline(text: String) -> Vec<FFIOutlineHeading> {
+    outline::extract_outline(&text)
+}
+
+pub fn format_markdown(
+    text: String,
+    selection_start: i32,
+    selection_end: i32,
+    command: FormatCommand,
+) -> Option<FFITextEdit> {
+    editor::format_markdown(
+        &text,
+        selection_start.max(0) as usize,
+        selection_end.max(0) as usize,
+        &command,
+    )
+}
+
+pub fn html_to_markdown(html: String, base_url: Option<String>) -> String {
+    html_to_markdown::html_to_markdown(&html, base_url.as_deref())
+}
+
+pub fn note_statistics(text: String) -> FFINoteStatistics {
+    statistics::note_statistics(&text)
+}
+
+pub fn handle_newline(text: String, cursor: i32) -> Option<FFITextEdit> {
+    editor::handle_newline(&text, cursor.max(0) as usize)
+}
+
+pub fn indent(
+    text: String,
+    selection_start: i32,
+    selection_end: i32,
+    outdent: bool,
+) -> Option<FFITextEdit> {
+    editor::indent(
+        &text,
+        selection_start.max(0) as usize,
+        selection_end.max(0) as usize,
+        outdent,
+    )
+}
+
+/// What `duplicate_folder` has created so far.
+#[derive(Default)]
+struct FolderCopy {
+    /// The copy of each copied resource, `None` if the resources aren't copied.
+    resource_copies: Option<HashMap<String, String>>,
+    folder_ids: Vec<String>,
+    note_ids: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -334,12 +1181,22 @@ pub enum MarkdownTagRange {
     Link {
         start: i32,
         end: i32,
-        url_offset: i32,
+        text_start: i32,
+        text_end: i32,
+        url_start: i32,
+        url_end: i32,
+        title_start: i32,
+        title_end: i32,
     },
     Image {
         start: i32,
         end: i32,
-        url_offset: i32,
+        text_start: i32,
+        text_end: i32,
+        url_start: i32,
+        url_end: i32,
+        title_start: i32,
+        title_end: i32,
     },
     Rule {
         start: i32,
@@ -358,21 +1215,44 @@ pub enum MarkdownTagRange {
         start: i32,
         end: i32,
     },
+    Math {
+        start: i32,
+        end: i32,
+        display: bool,
+    },
+    Html {
+        start: i32,
+        end: i32,
+    },
+    FootnoteReference {
+        start: i32,
+        end: i32,
+    },
+    FootnoteDefinition {
+        start: i32,
+        end: i32,
+        label_end: i32,
+    },
+    Autolink {
+        start: i32,
+        end: i32,
+    },
+    ReferenceLink {
+        start: i32,
+        end: i32,
+        label_start: i32,
+        label_end: i32,
+        url: String,
+        is_image: bool,
+    },
 }
 
 pub fn parse_markdown(s: String) -> Vec<MarkdownTagRange> {
     use pulldown_cmark::{CodeBlockKind, Event, LinkType, Options, Parser, Tag};
     let mut tag_ranges: Vec<MarkdownTagRange> = Vec::new();
-    let mut utf8_to_uft16_offsets: Vec<usize> = Vec::with_capacity(s.len());
-    let mut offset: usize = 0;
-    for c in s.chars() {
-        for _ in 0..c.len_utf8() {
-            utf8_to_uft16_offsets.push(offset);
-        }
-        offset += c.len_utf16();
-    }
-    utf8_to_uft16_offsets.push(offset);
-    let parser = Parser::new_ext(&s, Options::all());
+    let utf8_to_uft16_offsets = utf16::utf8_to_utf16_offsets(&s);
+    let masked = MaskedText::new(&s);
+    let parser = Parser::new_ext(&masked.text, Options::all());
     let mut list_nested_level: i32 = 0;
     let mut is_ordered_list: bool = false;
     for (event, range) in parser.into_offset_iter() {
@@ -406,49 +1286,89 @@ pub fn parse_markdown(s: String) -> Vec<MarkdownTagRange> {
                         ordered: is_ordered_list,
                     },
                     Tag::Paragraph => MarkdownTagRange::Paragraph { start, end },
-                    Tag::Link(link_type, url, title) => {
+                    Tag::Link(LinkType::Autolink | LinkType::Email, _, _) => {
+                        MarkdownTagRange::Autolink { start, end }
+                    }
+                    Tag::Link(link_type, url, _) => {
                         if link_type == LinkType::Inline {
-                            let url_offset = if title.is_empty() {
-                                1 + url.len()
-                            } else {
-                                2 + url.len() + title.len()
+                            let Some(ranges) = link::inline_link_ranges(&masked.text, range, false)
+                            else {
+                                continue;
                             };
                             MarkdownTagRange::Link {
                                 start,
                                 end,
-                                url_offset: utf8_to_uft16_offsets[range.end - url_offset] as i32,
+                                text_start: utf8_to_uft16_offsets[ranges.text.start] as i32,
+                                text_end: utf8_to_uft16_offsets[ranges.text.end] as i32,
+                                url_start: utf8_to_uft16_offsets[ranges.destination.start] as i32,
+                                url_end: utf8_to_uft16_offsets[ranges.destination.end] as i32,
+                                title_start: utf8_to_uft16_offsets[ranges.title.start] as i32,
+                                title_end: utf8_to_uft16_offsets[ranges.title.end] as i32,
                             }
                         } else {
-                            continue;
+                            reference_link_range(
+                                &s,
+                                range,
+                                link_type,
+                                url.to_string(),
+                                false,
+                                &utf8_to_uft16_offsets,
+                            )
                         }
                     }
-                    Tag::Image(link_type, url, title) => {
+                    Tag::Image(link_type, url, _) => {
                         if link_type == LinkType::Inline {
-                            let url_offset = if title.is_empty() {
-                                1 + url.len()
-                            } else {
-                                2 + url.len() + title.len()
+                            let Some(ranges) = link::inline_link_ranges(&masked.text, range, true)
+                            else {
+                                continue;
                             };
                             MarkdownTagRange::Image {
                                 start,
                                 end,
-                                url_offset: utf8_to_uft16_offsets[range.end - url_offset] as i32,
+                                text_start: utf8_to_uft16_offsets[ranges.text.start] as i32,
+                                text_end: utf8_to_uft16_offsets[ranges.text.end] as i32,
+                                url_start: utf8_to_uft16_offsets[ranges.destination.start] as i32,
+                                url_end: utf8_to_uft16_offsets[ranges.destination.end] as i32,
+                                title_start: utf8_to_uft16_offsets[ranges.title.start] as i32,
+                                title_end: utf8_to_uft16_offsets[ranges.title.end] as i32,
                             }
                         } else {
-                            continue;
+                            reference_link_range(
+                                &s,
+                                range,
+                                link_type,
+                                url.to_string(),
+                                true,
+                                &utf8_to_uft16_offsets,
+                            )
                         }
                     }
                     Tag::BlockQuote => MarkdownTagRange::BlockQuote { start, end },
                     Tag::CodeBlock(CodeBlockKind::Fenced(_)) => {
                         MarkdownTagRange::CodeBlock { start, end }
                     }
+                    Tag::FootnoteDefinition(_) => {
+                        let label_end = s[range.clone()]
+                            .find("]:")
+                            .map_or(range.start, |index| range.start + index + 2);
+                        MarkdownTagRange::FootnoteDefinition {
+                            start,
+                            end,
+                            label_end: utf8_to_uft16_offsets[label_end] as i32,
+                        }
+                    }
                     _ => continue,
                 };
                 tag_ranges.push(tag_range);
             }
-            Event::Code(_) => {
-                tag_ranges.push(MarkdownTagRange::InlineCode { start, end });
-            }
+            Event::Code(_) => match masked.span(&range) {
+                Some(span) => tag_ranges.push(MarkdownTagRange::Math {
+                    start,
+                    end,
+                    display: span.display,
+                }),
+                None => tag_ranges.push(MarkdownTagRange::InlineCode { start, end }),
+            },
             Event::End(tag) => match tag {
                 Tag::List(_) => {
                     list_nested_level -= 1;
@@ -465,12 +1385,367 @@ pub fn parse_markdown(s: String) -> Vec<MarkdownTagRange> {
                     is_checked,
                 });
             }
-            Event::Text(_)
-            | Event::Html(_)
-            | Event::FootnoteReference(_)
-            | Event::SoftBreak
-            | Event::HardBreak => {}
+            Event::Html(_) => {
+                tag_ranges.push(MarkdownTagRange::Html { start, end });
+            }
+            Event::FootnoteReference(_) => {
+                tag_ranges.push(MarkdownTagRange::FootnoteReference { start, end });
+            }
+            Event::Text(_) | Event::SoftBreak | Event::HardBreak => {}
         }
     }
     tag_ranges
 }
+
+/// Returns the range of a reference, collapsed or shortcut link, with the range of the label
+/// used to look up its definition.
+fn reference_link_range(
+    text: &str,
+    range: std::ops::Range<usize>,
+    link_type: pulldown_cmark::LinkType,
+    url: String,
+    is_image: bool,
+    utf16_offsets: &[usize],
+) -> MarkdownTagRange {
+    use pulldown_cmark::LinkType;
+    let mut end = range.end;
+    // The range of a collapsed link doesn't include the trailing `[]`.
+    if matches!(link_type, LinkType::Collapsed | LinkType::CollapsedUnknown)
+        && text[end..].starts_with("[]")
+    {
+        end += 2;
+    }
+    let text_start = range.start + if is_image { 2 } else { 1 };
+    let (label_start, label_end) = match link_type {
+        // The label can't contain brackets, so it starts after the last `[`.
+        LinkType::Reference | LinkType::ReferenceUnknown => {
+            let label_start = text[range.start..end - 1]
+                .rfind('[')
+                .map_or(text_start, |index| range.start + index + 1);
+            (label_start, end - 1)
+        }
+        LinkType::Collapsed | LinkType::CollapsedUnknown => (text_start, end - 3),
+        _ => (text_start, end - 1),
+    };
+    MarkdownTagRange::ReferenceLink {
+        start: utf16_offsets[range.start] as i32,
+        end: utf16_offsets[end] as i32,
+        label_start: utf16_offsets[label_start] as i32,
+        label_end: utf16_offsets[label_end] as i32,
+        url,
+        is_image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::test_util::{serve, Response, TempDir};
+
+    fn android_data(dir: &TempDir) -> RuslinAndroidData {
+        // The logger can only be set once per process.
+        static LOG_HANDLE: OnceLock<log4rs::Handle> = OnceLock::new();
+        let log_handle = LOG_HANDLE.get_or_init(|| {
+            let config = Config::builder()
+                .build(Root::builder().build(LevelFilter::Off))
+                .unwrap();
+            log4rs::init_config(config).unwrap()
+        });
+        RuslinAndroidData {
+            data: dir.data(),
+            resource_dir: dir.path.clone(),
+            recent_notes: RecentNotes::new(&dir.path),
+            note_meta: NoteMetaCache::default(),
+            rt: Runtime::new().unwrap(),
+            _log_handle: log_handle.clone(),
+        }
+    }
+
+    #[test]
+    fn parse_markdown_link_ranges() {
+        let text = "😀 [é](<ü x> 't')\n\n[a][r]\n\n[r]: https://example.com\n";
+        let ranges = parse_markdown(text.to_string());
+        let links: Vec<[i32; 8]> = ranges
+            .iter()
+            .filter_map(|range| match *range {
+                MarkdownTagRange::Link {
+                    start,
+                    end,
+                    text_start,
+                    text_end,
+                    url_start,
+                    url_end,
+                    title_start,
+                    title_end,
+                } => Some([
+                    start,
+                    end,
+                    text_start,
+                    text_end,
+                    url_start,
+                    url_end,
+                    title_start,
+                    title_end,
+                ]),
+                _ => None,
+            })
+            .collect();
+        // The emoji is two UTF-16 code units.
+        assert_eq!(links, [[3, 17, 4, 5, 8, 11, 14, 15]]);
+        // Reference links have no destination or title in the source of the link.
+        assert!(ranges.iter().any(|range| matches!(
+            range,
+            MarkdownTagRange::ReferenceLink {
+                start: 19,
+                end: 25,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn toggle_task() {
+        let dir = TempDir::new("toggle-task");
+        let data = android_data(&dir);
+        let note = Note::new(None, "Tasks".to_string(), "- [ ] a\n- [x] b\n".to_string());
+        data.data
+            .db
+            .replace_note(&note, UpdateSource::LocalEdit)
+            .unwrap();
+
+        let toggled = data.toggle_task(note.id.clone(), 10).unwrap();
+        assert_eq!(toggled.body, "- [ ] a\n- [ ] b\n");
+        let toggled = data.toggle_task(note.id.clone(), 2).unwrap();
+        assert_eq!(toggled.body, "- [x] a\n- [ ] b\n");
+        assert_eq!(data.data.db.load_note(&note.id).unwrap().body, toggled.body);
+        assert!(data.toggle_task(note.id, 3).is_err());
+    }
+
+    #[test]
+    fn set_pinned() {
+        let dir = TempDir::new("set-pinned");
+        let data = android_data(&dir);
+        let note = Note::new(None, "Note".to_string(), String::new());
+        let mut other = Note::new(None, "Other".to_string(), String::new());
+        other.application_data = "[]".to_string();
+        for note in [&note, &other] {
+            data.data
+                .db
+                .replace_note(note, UpdateSource::LocalEdit)
+                .unwrap();
+        }
+        let pinned_ids = || {
+            let options = FFINoteListOptions {
+                sort_key: NoteSortKey::Title,
+                direction: SortDirection::Ascending,
+                offset: 0,
+                limit: None,
+                show_completed_todos: true,
+                uncompleted_todos_first: false,
+                excerpt_length: 0,
+                pinned_first: false,
+            };
+            let notes = data.load_pinned_notes(options).unwrap();
+            notes.into_iter().map(|note| note.id).collect::<Vec<_>>()
+        };
+        // Caches the meta of the notes.
+        assert!(pinned_ids().is_empty());
+
+        // Application data that isn't a JSON object fails the batch before any write.
+        assert!(data
+            .set_pinned(vec![note.id.clone(), other.id.clone()], true)
+            .is_err());
+        assert!(pinned_ids().is_empty());
+
+        // Pinning doesn't change `user_updated_time`, which the cache is keyed by.
+        data.set_pinned(vec![note.id.clone()], true).unwrap();
+        assert_eq!(pinned_ids(), std::slice::from_ref(&note.id));
+        let pinned = data.data.db.load_note(&note.id).unwrap();
+        assert_eq!(
+            pinned.user_updated_time.timestamp_millis(),
+            note.user_updated_time.timestamp_millis()
+        );
+
+        data.set_pinned(vec![note.id.clone()], false).unwrap();
+        assert!(pinned_ids().is_empty());
+    }
+
+    #[test]
+    fn load_recent_notes() {
+        let dir = TempDir::new("load-recent-notes");
+        let data = android_data(&dir);
+        let notes: Vec<Note> = ["a", "b", "c"]
+            .into_iter()
+            .map(|title| Note::new(None, title.to_string(), String::new()))
+            .collect();
+        for note in &notes {
+            data.data
+                .db
+                .replace_note(note, UpdateSource::LocalEdit)
+                .unwrap();
+            data.mark_note_opened(note.id.clone());
+        }
+        let titles = |limit| {
+            let notes = data.load_recent_notes(limit).unwrap();
+            notes.into_iter().map(|note| note.title).collect::<Vec<_>>()
+        };
+        assert_eq!(titles(10), ["c", "b", "a"]);
+        assert_eq!(titles(2), ["c", "b"]);
+        assert!(titles(0).is_empty());
+
+        // Deleted, e.g. by a sync, without going through `delete_note`.
+        data.data
+            .db
+            .delete_note(&notes[1].id, UpdateSource::LocalEdit)
+            .unwrap();
+        assert_eq!(titles(10), ["c", "a"]);
+        let entries = data.recent_notes.entries();
+        assert!(entries.iter().all(|entry| entry.id != notes[1].id));
+
+        data.clear_recent_notes();
+        assert!(titles(10).is_empty());
+    }
+
+    #[test]
+    fn move_folder_to_root() {
+        let dir = TempDir::new("move-folder");
+        let data = android_data(&dir);
+        let parent = Folder::new("Parent".to_string(), None);
+        let child = Folder::new("Child".to_string(), Some(parent.id.clone()));
+        for folder in [&parent, &child] {
+            data.data
+                .db
+                .replace_folder(folder, UpdateSource::LocalEdit)
+                .unwrap();
+        }
+
+        data.move_folder(child.id.clone(), Some(String::new()))
+            .unwrap();
+        let folders = data.data.db.load_folders().unwrap();
+        let moved = folders.iter().find(|f| f.id == child.id).unwrap();
+        assert_eq!(moved.parent_id, None);
+
+        assert!(data
+            .move_folder(parent.id.clone(), Some(parent.id.clone()))
+            .is_err());
+    }
+
+    #[test]
+    fn copy_resource_without_extension() {
+        let dir = TempDir::new("copy-resource");
+        let data = android_data(&dir);
+        let resource = Resource::new(
+            "notes".to_string(),
+            "application/octet-stream".to_string(),
+            String::new(),
+            4,
+        );
+        data.data
+            .db
+            .replace_resource(&resource, UpdateSource::LocalEdit)
+            .unwrap();
+        std::fs::write(dir.path.join(&resource.id), b"data").unwrap();
+
+        let mut resource_copies = HashMap::new();
+        let body = format!("[notes](:/{})", resource.id);
+        data.copy_linked_resources(&body, &mut resource_copies)
+            .unwrap();
+        let copy_id = &resource_copies[&resource.id];
+        assert_eq!(std::fs::read(dir.path.join(copy_id)).unwrap(), b"data");
+    }
+
+    #[test]
+    fn download_images() {
+        let dir = TempDir::new("download-images");
+        let data = android_data(&dir);
+        let png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let server = serve(vec![
+            Response {
+                path: "/photo.png",
+                status: 200,
+                content_type: "image/png",
+                body: png.clone(),
+                content_length: true,
+            },
+            Response {
+                path: "/page",
+                status: 200,
+                content_type: "text/html",
+                body: b"<p>Not an image</p>".to_vec(),
+                content_length: true,
+            },
+        ]);
+        let image_url = format!("{server}/photo.png");
+        let urls = vec![
+            image_url.clone(),
+            format!("{server}/missing.png"),
+            format!("{server}/page"),
+            "data:image/png;base64,iVBORw0KGgo=".to_string(),
+        ];
+        let destinations = data.download_images(&urls).unwrap();
+        assert_eq!(destinations.len(), 1);
+        let id = destinations[&image_url].strip_prefix(":/").unwrap();
+        let resource = data.data.db.load_resource(id).unwrap();
+        assert_eq!(resource.title, "photo.png");
+        assert_eq!(resource.mime, "image/png");
+        assert_eq!(std::fs::read(data.resource_path(&resource)).unwrap(), png);
+    }
+
+    #[test]
+    fn clip_url_charset() {
+        let dir = TempDir::new("clip-url");
+        let data = android_data(&dir);
+        let server = serve(vec![Response {
+            path: "/article",
+            status: 200,
+            content_type: "text/html; charset=windows-1252",
+            body: b"<title>Caf\xe9</title><p>\x93Quoted\x94</p>".to_vec(),
+            content_length: true,
+        }]);
+        let url = format!("{server}/article");
+        let note = data.clip_url(url, None, ClipMode::FullPage).unwrap();
+        assert_eq!(note.title, "Caf\u{e9}");
+        assert_eq!(note.body, "\u{201c}Quoted\u{201d}");
+    }
+
+    #[test]
+    fn clip_url_bookmark() {
+        let dir = TempDir::new("clip-url");
+        let data = android_data(&dir);
+        let mut page = b"<title>Page</title><meta name=description content=About><p>".to_vec();
+        page.resize(download::MAX_PAGE_SIZE + 1, b'a');
+        let server = serve(vec![
+            Response {
+                path: "/page",
+                status: 200,
+                content_type: "text/html",
+                body: page,
+                content_length: true,
+            },
+            Response {
+                path: "/file.pdf",
+                status: 200,
+                content_type: "application/pdf",
+                body: b"%PDF-1.7".to_vec(),
+                content_length: true,
+            },
+        ]);
+        let url = format!("{server}/page");
+        let note = data
+            .clip_url(url.clone(), None, ClipMode::Bookmark)
+            .unwrap();
+        assert_eq!(note.title, "Page");
+        assert_eq!(note.body, format!("<{url}>\n\nAbout"));
+        assert!(data.clip_url(url, None, ClipMode::FullPage).is_err());
+
+        let url = format!("{server}/file.pdf");
+        let note = data
+            .clip_url(url.clone(), None, ClipMode::Bookmark)
+            .unwrap();
+        assert_eq!(note.title, url);
+        assert_eq!(note.body, format!("<{url}>"));
+        assert!(data.clip_url(url, None, ClipMode::Simplified).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+/// Source ranges of an inline link or image, without their delimiters.
+pub struct InlineLinkRanges {
+    pub text: Range<usize>,
+    pub destination: Range<usize>,
+    /// Empty at the end of the destination if there is no title.
+    pub title: Range<usize>,
+}
+
+/// Scans the source of an inline link or image, `[text](destination "title")`. Only ASCII
+/// delimiters are matched, so all offsets are on character boundaries.
+pub fn inline_link_ranges(
+    text: &str,
+    range: Range<usize>,
+    is_image: bool,
+) -> Option<InlineLinkRanges> {
+    let bytes = &text.as_bytes()[..range.end];
+    let text_start = range.start + if is_image { 2 } else { 1 };
+    let text_end = find_text_end(bytes, text_start)?;
+    if bytes.get(text_end + 1) != Some(&b'(') || bytes[range.end - 1] != b')' {
+        return None;
+    }
+
+    let mut index = skip_whitespace(bytes, text_end + 2);
+    let destination = if bytes.get(index) == Some(&b'<') {
+        let start = index + 1;
+        index = start;
+        while bytes.get(index)? != &b'>' {
+            index += if bytes[index] == b'\\' { 2 } else { 1 };
+        }
+        index += 1;
+        start..index - 1
+    } else {
+        let start = index;
+        let mut nest = 0;
+        while let Some(&c) = bytes.get(index) {
+            match c {
+                b'\\' => index += 1,
+                b'(' => nest += 1,
+                b')' if nest == 0 => break,
+                b')' => nest -= 1,
+                c if c.is_ascii_whitespace() => break,
+                _ => {}
+            }
+            index += 1;
+        }
+        start..index.min(bytes.len())
+    };
+
+    index = skip_whitespace(bytes, index);
+    let close = match bytes.get(index) {
+        Some(b'"') => b'"',
+        Some(b'\'') => b'\'',
+        Some(b'(') => b')',
+        _ => {
+            return Some(InlineLinkRanges {
+                text: text_start..text_end,
+                destination: destination.clone(),
+                title: destination.end..destination.end,
+            })
+        }
+    };
+    let title_start = index + 1;
+    index = title_start;
+    while bytes.get(index)? != &close {
+        index += if bytes[index] == b'\\' { 2 } else { 1 };
+    }
+    Some(InlineLinkRanges {
+        text: text_start..text_end,
+        destination,
+        title: title_start..index,
+    })
+}
+
+/// Returns the offset of the `]` closing the link text. Brackets in the text are balanced,
+/// except for escaped ones and ones in code spans.
+pub fn find_text_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut index = start;
+    let mut nest = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'`' => {
+                let ticks = bytes[index..].iter().take_while(|&&c| c == b'`').count();
+                index += ticks;
+                // An unclosed run of backticks is literal text.
+                if let Some(end) = find_backticks(bytes, index, ticks) {
+                    index = end + ticks;
+                }
+                continue;
+            }
+            b'[' => nest += 1,
+            b']' if nest == 0 => return Some(index),
+            b']' => nest -= 1,
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Finds the next run of exactly `ticks` backticks, from `start`.
+fn find_backticks(bytes: &[u8], start: usize, ticks: usize) -> Option<usize> {
+    let mut index = start;
+    while index < bytes.len() {
+        if bytes[index] == b'`' {
+            let run = bytes[index..].iter().take_while(|&&c| c == b'`').count();
+            if run == ticks {
+                return Some(index);
+            }
+            index += run;
+        } else {
+            index += 1;
+        }
+    }
+    None
+}
+
+fn skip_whitespace(bytes: &[u8], mut index: usize) -> usize {
+    while matches!(bytes.get(index), Some(c) if c.is_ascii_whitespace()) {
+        index += 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the text, destination and title of the link or image that is all of `text`.
+    fn parts(text: &str) -> Option<(&str, &str, &str)> {
+        let ranges = inline_link_ranges(text, 0..text.len(), text.starts_with('!'))?;
+        Some((
+            &text[ranges.text],
+            &text[ranges.destination],
+            &text[ranges.title],
+        ))
+    }
+
+    #[test]
+    fn destinations() {
+        assert_eq!(parts("[a](b)"), Some(("a", "b", "")));
+        assert_eq!(parts("[a]()"), Some(("a", "", "")));
+        assert_eq!(parts("[a](<b c>)"), Some(("a", "b c", "")));
+        assert_eq!(parts("[a](<b\\>c>)"), Some(("a", "b\\>c", "")));
+        assert_eq!(parts("[a](b\\)c)"), Some(("a", "b\\)c", "")));
+        assert_eq!(parts("[a](b(c)d)"), Some(("a", "b(c)d", "")));
+        assert_eq!(parts("[a](b&amp;c)"), Some(("a", "b&amp;c", "")));
+        assert_eq!(parts("![alt](image.png)"), Some(("alt", "image.png", "")));
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(parts("[a](  b  )"), Some(("a", "b", "")));
+        assert_eq!(parts("[a](\n  b\n  \"t\"\n)"), Some(("a", "b", "t")));
+        let text = "[a](b )";
+        let ranges = inline_link_ranges(text, 0..text.len(), false).unwrap();
+        // Without a title, the title range is empty at the end of the destination.
+        assert_eq!(ranges.title, 5..5);
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(parts("[a](b \"t\")"), Some(("a", "b", "t")));
+        assert_eq!(parts("[a](b 't')"), Some(("a", "b", "t")));
+        assert_eq!(parts("[a](b (t))"), Some(("a", "b", "t")));
+        assert_eq!(parts("[a](b \"t \\\" u\")"), Some(("a", "b", "t \\\" u")));
+        assert_eq!(parts("[a](b 'it\"s')"), Some(("a", "b", "it\"s")));
+        assert_eq!(parts("[a](b \"t&quot;\")"), Some(("a", "b", "t&quot;")));
+        assert_eq!(parts("![alt](<a b> 't')"), Some(("alt", "a b", "t")));
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(parts("[a [b] c](d)"), Some(("a [b] c", "d", "")));
+        assert_eq!(parts("[a \\] b](c)"), Some(("a \\] b", "c", "")));
+        assert_eq!(parts("[a `]` b](c)"), Some(("a `]` b", "c", "")));
+        assert_eq!(parts("[a ` b](c)"), Some(("a ` b", "c", "")));
+        assert_eq!(
+            parts("[![img](i.png)](d)"),
+            Some(("![img](i.png)", "d", ""))
+        );
+    }
+
+    #[test]
+    fn reference_links() {
+        assert!(parts("[a][b]").is_none());
+        assert!(parts("[a][]").is_none());
+        assert!(parts("[a]").is_none());
+    }
+
+    #[test]
+    fn multibyte() {
+        assert_eq!(parts("[日本 😀](ü/é \"ä\")"), Some(("日本 😀", "ü/é", "ä")));
+        let text = "😀 [é](ü)";
+        let ranges = inline_link_ranges(text, 5..text.len(), false).unwrap();
+        assert_eq!(&text[ranges.text], "é");
+        assert_eq!(&text[ranges.destination], "ü");
+    }
+}
@@ -0,0 +1,357 @@
+//! `$inline$` and `$$display$$` math, as used by Joplin.
+//!
+//! pulldown-cmark doesn't know about math, so underscores and asterisks in formulas would
+//! be parsed as emphasis. Before parsing, the `$` delimiters of every math span are replaced
+//! with backticks, which turns the span into a code span of the same length. The parsers
+//! then treat a code span that starts at a math span as math.
+
+use std::ops::Range;
+
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag};
+
+pub struct MathSpan {
+    /// Range of the span in the source, including the delimiters.
+    pub range: Range<usize>,
+    pub display: bool,
+}
+
+impl MathSpan {
+    /// The unmodified TeX source.
+    pub fn tex<'a>(&self, text: &'a str) -> &'a str {
+        let delimiter_len = if self.display { 2 } else { 1 };
+        &text[self.range.start + delimiter_len..self.range.end - delimiter_len]
+    }
+}
+
+pub struct MaskedText {
+    /// The source with the math delimiters replaced, to be given to the parser. It has the
+    /// same length and character boundaries as the source.
+    pub text: String,
+    /// Math spans, sorted by their start.
+    pub spans: Vec<MathSpan>,
+}
+
+impl MaskedText {
+    pub fn new(text: &str) -> Self {
+        let spans = find_math_spans(text);
+        Self {
+            text: mask(text, &spans),
+            spans,
+        }
+    }
+
+    /// Returns the math span of a code span event. Only the start is compared, as the parser
+    /// can extend the range over trailing whitespace, e.g. at the end of a heading.
+    pub fn span(&self, range: &Range<usize>) -> Option<&MathSpan> {
+        self.spans
+            .binary_search_by_key(&range.start, |span| span.range.start)
+            .ok()
+            .map(|index| &self.spans[index])
+    }
+}
+
+fn mask(text: &str, spans: &[MathSpan]) -> String {
+    let mut bytes = text.as_bytes().to_vec();
+    for span in spans {
+        let delimiter_len = if span.display { 2 } else { 1 };
+        for offset in 0..delimiter_len {
+            bytes[span.range.start + offset] = b'`';
+            bytes[span.range.end - 1 - offset] = b'`';
+        }
+    }
+    // Only ASCII `$` were replaced with ASCII backticks.
+    String::from_utf8(bytes).unwrap()
+}
+
+/// Where math can be in the source, from a single parse.
+struct SourceLayout {
+    /// Code, code blocks, HTML and autolinks, in which `$` is not a delimiter.
+    excluded: Vec<Range<usize>>,
+    /// Text, the only place for delimiters. This keeps `$` in link destinations as is.
+    texts: Vec<Range<usize>>,
+    /// Starts and ends of blocks. A span can't cross them, and a backtick only pairs with
+    /// another one of the same block.
+    block_boundaries: Vec<usize>,
+}
+
+impl SourceLayout {
+    fn new(text: &str) -> Self {
+        let mut layout = Self {
+            excluded: Vec::new(),
+            texts: Vec::new(),
+            block_boundaries: Vec::new(),
+        };
+        for (event, range) in Parser::new_ext(text, Options::all()).into_offset_iter() {
+            match event {
+                Event::Code(_)
+                | Event::Html(_)
+                | Event::Start(Tag::CodeBlock(_))
+                | Event::Start(Tag::Link(LinkType::Autolink | LinkType::Email, ..)) => {
+                    layout.excluded.push(range)
+                }
+                Event::Text(_) => layout.texts.push(range),
+                Event::Start(tag) if !is_inline(&tag) => layout.block_boundaries.push(range.start),
+                Event::End(tag) if !is_inline(&tag) => layout.block_boundaries.push(range.end),
+                _ => {}
+            }
+        }
+        layout.excluded.sort_by_key(|range| range.start);
+        layout.texts.sort_by_key(|range| range.start);
+        layout.block_boundaries.sort_unstable();
+        layout
+    }
+
+    fn is_text(&self, offset: usize) -> bool {
+        let index = self.texts.partition_point(|range| range.end <= offset);
+        self.texts
+            .get(index)
+            .is_some_and(|range| range.start <= offset)
+    }
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+    )
+}
+
+/// Finds the math spans whose delimiters can be replaced with backticks, so that each one
+/// becomes exactly one code span.
+fn find_math_spans(text: &str) -> Vec<MathSpan> {
+    let bytes = text.as_bytes();
+    let layout = SourceLayout::new(text);
+    let mut excluded_index = 0;
+    let mut boundary_index = 0;
+    // Whether a backtick run of length 1 or 2 that isn't a code span was seen in the
+    // current block. It would pair with the backticks of a later delimiter of that length.
+    let mut stray_backticks = [false; 2];
+    let mut spans: Vec<MathSpan> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        while layout
+            .block_boundaries
+            .get(boundary_index)
+            .is_some_and(|boundary| *boundary <= i)
+        {
+            boundary_index += 1;
+            stray_backticks = [false; 2];
+        }
+        while excluded_index < layout.excluded.len() && layout.excluded[excluded_index].end <= i {
+            excluded_index += 1;
+        }
+        if let Some(range) = layout.excluded.get(excluded_index) {
+            if range.start <= i {
+                i = range.end;
+                continue;
+            }
+        }
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let run = bytes[i..].iter().take_while(|c| **c == b'`').count();
+                if let Some(seen) = stray_backticks.get_mut(run - 1) {
+                    *seen = true;
+                }
+                i += run;
+            }
+            b'$' => {
+                let next_excluded = layout
+                    .excluded
+                    .get(excluded_index)
+                    .map(|range| range.start)
+                    .unwrap_or(bytes.len());
+                let next_boundary = layout
+                    .block_boundaries
+                    .get(boundary_index)
+                    .copied()
+                    .unwrap_or(bytes.len());
+                let limit = next_excluded.min(next_boundary);
+                let display = bytes.get(i + 1) == Some(&b'$');
+                let end = if display {
+                    find_display_end(bytes, i + 2, limit)
+                } else {
+                    find_inline_end(bytes, i + 1, limit)
+                };
+                let span = end
+                    .map(|end| MathSpan {
+                        range: i..end,
+                        display,
+                    })
+                    .filter(|span| {
+                        let delimiter_len = if span.display { 2 } else { 1 };
+                        let (start, end) = (span.range.start, span.range.end);
+                        layout.is_text(start)
+                            && layout.is_text(end - 1)
+                            && !stray_backticks[delimiter_len - 1]
+                            // Adjacent backticks would make the delimiter a longer run.
+                            && !(start > 0 && matches!(bytes[start - 1], b'`' | b'$'))
+                            && !matches!(bytes.get(end), Some(b'`'))
+                    });
+                match span {
+                    Some(span) => {
+                        i = span.range.end;
+                        spans.push(span);
+                    }
+                    // Skip the whole run of `$`, e.g. `$$$`.
+                    None => {
+                        while bytes.get(i) == Some(&b'$') {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    spans
+}
+
+/// Returns the end of a `$$...$$` span whose content starts at `start`.
+fn find_display_end(bytes: &[u8], start: usize, limit: usize) -> Option<usize> {
+    if bytes.get(start) == Some(&b'$') {
+        return None;
+    }
+    let mut i = start;
+    while i + 1 < limit {
+        match bytes[i] {
+            // Backslashes don't escape in code spans, so even `\`` would end it.
+            b'\\' if bytes.get(i + 1) != Some(&b'`') => i += 2,
+            b'`' | b'\\' => return None,
+            b'\n' if is_blank_line(bytes, i + 1) => return None,
+            b'$' if bytes[i + 1] == b'$' => {
+                if bytes[start..i].iter().all(u8::is_ascii_whitespace) {
+                    return None;
+                }
+                return Some(i + 2);
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Returns the end of a `$...$` span whose content starts at `start`. Like Pandoc, the
+/// content can't start or end with whitespace, and the closing `$` can't be followed by a
+/// digit, so that prices like `$5 and $10` stay text.
+fn find_inline_end(bytes: &[u8], start: usize, limit: usize) -> Option<usize> {
+    match bytes.get(start) {
+        Some(c) if !c.is_ascii_whitespace() => {}
+        _ => return None,
+    }
+    let mut i = start;
+    while i < limit {
+        match bytes[i] {
+            // Backslashes don't escape in code spans, so even `\`` would end it.
+            b'\\' if bytes.get(i + 1) != Some(&b'`') => i += 2,
+            b'`' | b'\\' => return None,
+            b'\n' if is_blank_line(bytes, i + 1) => return None,
+            b'$' => {
+                if !bytes[i - 1].is_ascii_whitespace()
+                    && !bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+                {
+                    return Some(i + 1);
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn is_blank_line(bytes: &[u8], line_start: usize) -> bool {
+    bytes[line_start..]
+        .iter()
+        .take_while(|c| **c != b'\n')
+        .all(u8::is_ascii_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the source of each math span, after checking that the parser sees it as
+    /// exactly one code span of the masked text.
+    fn math(text: &str) -> Vec<&str> {
+        let masked = MaskedText::new(text);
+        let code_ranges: Vec<Range<usize>> = Parser::new_ext(&masked.text, Options::all())
+            .into_offset_iter()
+            .filter_map(|(event, range)| matches!(event, Event::Code(_)).then_some(range))
+            .collect();
+        masked
+            .spans
+            .iter()
+            .map(|span| {
+                assert!(code_ranges.contains(&span.range), "{text:?}");
+                &text[span.range.clone()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inline_and_display() {
+        assert_eq!(math("a $x_1$ b"), ["$x_1$"]);
+        assert_eq!(MaskedText::new("a $x_1$ b").text, "a `x_1` b");
+        assert_eq!(math("$$\\sum_{i=1}^n i$$"), ["$$\\sum_{i=1}^n i$$"]);
+        let masked = MaskedText::new("$a *b* c$");
+        assert_eq!(masked.spans[0].tex("$a *b* c$"), "a *b* c");
+    }
+
+    #[test]
+    fn currency() {
+        assert!(math("$5 and $10").is_empty());
+        assert!(math("from $ 5 to $ 10").is_empty());
+        assert!(math("$x $5").is_empty());
+    }
+
+    #[test]
+    fn escaped_dollar() {
+        assert_eq!(math("\\$x$ and $y$"), ["$y$"]);
+        assert_eq!(math("$a\\$b$"), ["$a\\$b$"]);
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(math("`$x$` and $y$"), ["$y$"]);
+        assert!(math("$a `b` c$").is_empty());
+        assert_eq!(math("```\n$x$\n```\n\n$y$"), ["$y$"]);
+        assert!(math("    $x$").is_empty());
+        assert!(math("<span title=\"$x$\">").is_empty());
+        assert!(math("<ab:$a$>").is_empty());
+    }
+
+    #[test]
+    fn display_across_lines() {
+        let text = "Before\n\n$$\na_b\n\\\\ c\n$$\n\nAfter";
+        assert_eq!(math(text), ["$$\na_b\n\\\\ c\n$$"]);
+        assert_eq!(math("> $$a\n> b$$"), ["$$a\n> b$$"]);
+        // A blank line ends the paragraph.
+        assert!(math("$$\na\n\nb\n$$").is_empty());
+    }
+
+    #[test]
+    fn unclosed() {
+        assert!(math("$x and more").is_empty());
+        assert!(math("$$x").is_empty());
+        assert!(math("a $x\n\nb$").is_empty());
+        assert!(math("$$$").is_empty());
+        assert_eq!(math("$x $y$"), ["$x $y$"]);
+    }
+
+    #[test]
+    fn stray_backticks() {
+        assert!(math("a ` b $x$ c").is_empty());
+        assert_eq!(math("a `` b $x$ c"), ["$x$"]);
+        assert_eq!(math("a $x$ ` b"), ["$x$"]);
+        assert_eq!(math("a `\n\n$x$"), ["$x$"]);
+        assert!(math("`$x$").is_empty());
+        assert_eq!(math("$a$$b$"), ["$a$"]);
+    }
+
+    #[test]
+    fn link_destinations() {
+        assert!(math("[a](http://example.com/$a$)").is_empty());
+        assert_eq!(math("[$a$](http://example.com)"), ["$a$"]);
+    }
+}
@@ -0,0 +1,252 @@
+use std::cmp::Ordering;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use ruslin_data::{AbbrNote, ModelType, Note, RuslinData};
+
+use crate::ffi::{FFINoteListOptions, FFINoteSummary, NoteSortKey, SortDirection};
+use crate::note_meta::NoteMeta;
+use crate::{pin, plain_text};
+
+pub struct NoteListItem {
+    pub abbr: AbbrNote,
+    /// Only loaded when `needs_note_meta` is true, the default otherwise.
+    pub meta: NoteMeta,
+}
+
+/// Whether the options need fields that `AbbrNote` doesn't have.
+pub fn needs_note_meta(options: &FFINoteListOptions) -> bool {
+    options.sort_key == NoteSortKey::Order
+        || !options.show_completed_todos
+        || options.uncompleted_todos_first
+        || options.pinned_first
+}
+
+/// Filters, sorts and paginates the notes. Ties are broken by id so that pages are stable.
+pub fn list_notes(mut items: Vec<NoteListItem>, options: &FFINoteListOptions) -> Vec<NoteListItem> {
+    if !options.show_completed_todos {
+        items.retain(|item| !item.meta.is_completed_todo());
+    }
+    items.sort_by(|a, b| {
+        let ordering = match options.sort_key {
+            NoteSortKey::Title => a
+                .abbr
+                .title
+                .to_lowercase()
+                .cmp(&b.abbr.title.to_lowercase()),
+            NoteSortKey::UpdatedTime => a
+                .abbr
+                .user_updated_time
+                .timestamp_millis()
+                .cmp(&b.abbr.user_updated_time.timestamp_millis()),
+            NoteSortKey::CreatedTime => a
+                .abbr
+                .user_created_time
+                .timestamp_millis()
+                .cmp(&b.abbr.user_created_time.timestamp_millis()),
+            NoteSortKey::Order => a.meta.order.cmp(&b.meta.order),
+        };
+        let ordering = match options.direction {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        };
+        let todo_ordering = if options.uncompleted_todos_first {
+            b.meta
+                .is_uncompleted_todo()
+                .cmp(&a.meta.is_uncompleted_todo())
+        } else {
+            Ordering::Equal
+        };
+        let pinned_ordering = if options.pinned_first {
+            b.meta.is_pinned.cmp(&a.meta.is_pinned)
+        } else {
+            Ordering::Equal
+        };
+        pinned_ordering
+            .then(todo_ordering)
+            .then(ordering)
+            .then_with(|| a.abbr.id.cmp(&b.abbr.id))
+    });
+    let offset = options.offset.max(0) as usize;
+    let limit = options
+        .limit
+        .map(|limit| limit.max(0) as usize)
+        .unwrap_or(usize::MAX);
+    items.into_iter().skip(offset).take(limit).collect()
+}
+
+pub fn note_summary(data: &RuslinData, note: Note, excerpt_length: usize) -> FFINoteSummary {
+    let mut first_image_resource_id: Option<String> = None;
+    let mut has_attachments = false;
+    for event in Parser::new_ext(&note.body, Options::all()) {
+        let (dest, is_image) = match event {
+            Event::Start(Tag::Image(_, dest, _)) => (dest, true),
+            Event::Start(Tag::Link(_, dest, _)) => (dest, false),
+            _ => continue,
+        };
+        if dest.len() != 34 || !dest.starts_with(":/") {
+            continue;
+        }
+        let id = &dest[2..];
+        if is_image {
+            has_attachments = true;
+            if first_image_resource_id.is_none() {
+                first_image_resource_id = Some(id.to_string());
+            }
+        } else if !has_attachments {
+            has_attachments = data
+                .db
+                .load_sync_item(id)
+                .map(|t| !matches!(t.item_type, ModelType::Note))
+                .unwrap_or(false);
+        }
+    }
+    FFINoteSummary {
+        excerpt: plain_text::excerpt(&note.body, excerpt_length),
+        id: note.id,
+        parent_id: note.parent_id,
+        title: note.title,
+        user_created_time: note.user_created_time.timestamp_millis(),
+        user_updated_time: note.user_updated_time.timestamp_millis(),
+        is_conflict: note.is_conflict,
+        is_todo: note.is_todo,
+        todo_due: note.todo_due,
+        todo_completed: note.todo_completed,
+        first_image_resource_id,
+        has_attachments,
+        is_pinned: pin::is_pinned(&note.application_data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruslin_data::{DateTimeTimestamp, Resource, UpdateSource};
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn options(sort_key: NoteSortKey, direction: SortDirection) -> FFINoteListOptions {
+        FFINoteListOptions {
+            sort_key,
+            direction,
+            offset: 0,
+            limit: None,
+            show_completed_todos: true,
+            uncompleted_todos_first: false,
+            excerpt_length: 20,
+            pinned_first: false,
+        }
+    }
+
+    /// Notes "a" to "d", whose title, times and order sort them differently.
+    fn items() -> Vec<NoteListItem> {
+        [
+            ("a", "Banana", 3, 2, 1),
+            ("b", "apple", 1, 4, 3),
+            ("c", "Cherry", 4, 1, 4),
+            ("d", "date", 2, 3, 2),
+        ]
+        .into_iter()
+        .map(|(id, title, created, updated, order)| NoteListItem {
+            abbr: AbbrNote {
+                id: id.to_string(),
+                parent_id: None,
+                title: title.to_string(),
+                user_created_time: DateTimeTimestamp::from_timestamp_millis(created),
+                user_updated_time: DateTimeTimestamp::from_timestamp_millis(updated),
+            },
+            meta: NoteMeta {
+                order,
+                ..Default::default()
+            },
+        })
+        .collect()
+    }
+
+    fn ids(items: &[NoteListItem]) -> Vec<&str> {
+        items.iter().map(|item| item.abbr.id.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_keys() {
+        let cases = [
+            (NoteSortKey::Title, ["b", "a", "c", "d"]),
+            (NoteSortKey::CreatedTime, ["b", "d", "a", "c"]),
+            (NoteSortKey::UpdatedTime, ["c", "a", "d", "b"]),
+            (NoteSortKey::Order, ["a", "d", "b", "c"]),
+        ];
+        for (sort_key, expected) in cases {
+            let ascending = list_notes(items(), &options(sort_key, SortDirection::Ascending));
+            assert_eq!(ids(&ascending), expected, "{sort_key:?}");
+            let descending = list_notes(items(), &options(sort_key, SortDirection::Descending));
+            let mut reversed = expected;
+            reversed.reverse();
+            assert_eq!(ids(&descending), reversed, "{sort_key:?}");
+        }
+    }
+
+    #[test]
+    fn todos_and_pins() {
+        let mut items = items();
+        items[0].meta.is_todo = true;
+        items[1].meta.is_todo = true;
+        items[1].meta.todo_completed = true;
+        items[3].meta.is_pinned = true;
+        let mut options = options(NoteSortKey::Title, SortDirection::Ascending);
+        options.show_completed_todos = false;
+        options.uncompleted_todos_first = true;
+        options.pinned_first = true;
+        assert!(needs_note_meta(&options));
+        assert_eq!(ids(&list_notes(items, &options)), ["d", "a", "c"]);
+    }
+
+    #[test]
+    fn pages() {
+        let mut options = options(NoteSortKey::Title, SortDirection::Ascending);
+        options.offset = 1;
+        options.limit = Some(2);
+        assert_eq!(ids(&list_notes(items(), &options)), ["a", "c"]);
+        options.offset = 3;
+        assert_eq!(ids(&list_notes(items(), &options)), ["d"]);
+        options.offset = 10;
+        assert!(list_notes(items(), &options).is_empty());
+        options.offset = -1;
+        options.limit = Some(-1);
+        assert!(list_notes(items(), &options).is_empty());
+    }
+
+    #[test]
+    fn summary() {
+        let dir = TempDir::new("note-summary");
+        let data = dir.data();
+        let resource = Resource::new(
+            "report.pdf".to_string(),
+            "application/pdf".to_string(),
+            "pdf".to_string(),
+            1,
+        );
+        data.db
+            .replace_resource(&resource, UpdateSource::LocalEdit)
+            .unwrap();
+        // The external link has the length of a resource link.
+        let external = "https://example.com/0123456789abcd";
+        assert_eq!(external.len(), 2 + resource.id.len());
+
+        let body = format!("# Title\n\nSome **bold** text [site]({external}) and more words");
+        let note = Note::new(None, "Note".to_string(), body);
+        let summary = note_summary(&data, note, 20);
+        assert_eq!(summary.excerpt, "Title Some bold text");
+        assert!(!summary.has_attachments);
+        assert_eq!(summary.first_image_resource_id, None);
+
+        let body = format!("[report](:/{})", resource.id);
+        let summary = note_summary(&data, Note::new(None, "Note".to_string(), body), 20);
+        assert!(summary.has_attachments);
+        assert_eq!(summary.first_image_resource_id, None);
+
+        let body = format!("Text\n\n![](:/{})", resource.id);
+        let summary = note_summary(&data, Note::new(None, "Note".to_string(), body), 20);
+        assert!(summary.has_attachments);
+        assert_eq!(summary.first_image_resource_id, Some(resource.id));
+        assert_eq!(summary.excerpt, "Text");
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use ruslin_data::{AbbrNote, DatabaseError, Note};
+
+use crate::pin;
+
+/// The fields of a note that lists count, filter and sort on but `AbbrNote` doesn't have.
+#[derive(Clone, Copy, Default)]
+pub struct NoteMeta {
+    pub is_todo: bool,
+    pub todo_completed: bool,
+    pub order: i64,
+    pub is_pinned: bool,
+    pub is_conflict: bool,
+}
+
+impl NoteMeta {
+    pub fn new(note: &Note) -> Self {
+        Self {
+            is_todo: note.is_todo,
+            todo_completed: note.todo_completed,
+            order: note.order,
+            is_pinned: pin::is_pinned(&note.application_data),
+            is_conflict: note.is_conflict,
+        }
+    }
+
+    pub fn is_uncompleted_todo(&self) -> bool {
+        self.is_todo && !self.todo_completed
+    }
+
+    pub fn is_completed_todo(&self) -> bool {
+        self.is_todo && self.todo_completed
+    }
+}
+
+/// Caches `NoteMeta` so that lists only need the abbreviated rows, since the database has
+/// no bulk query for these fields. A note is loaded once, and again after it changes:
+/// entries are keyed by `user_updated_time`, and writes through this crate invalidate them.
+#[derive(Default)]
+pub struct NoteMetaCache {
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, (i64, NoteMeta)>,
+    /// Bumped by invalidations, so notes loaded while a write happened aren't cached.
+    generation: u64,
+}
+
+impl NoteMetaCache {
+    /// Returns the meta of each note, loading the notes that aren't cached yet.
+    pub fn get(
+        &self,
+        notes: &[AbbrNote],
+        load_note: impl Fn(&str) -> Result<Note, DatabaseError>,
+    ) -> Result<Vec<NoteMeta>, DatabaseError> {
+        let (missing, generation) = {
+            let state = self.lock();
+            let missing: Vec<&AbbrNote> = notes
+                .iter()
+                .filter(|note| {
+                    state.entries.get(&note.id).map(|(time, _)| *time)
+                        != Some(note.user_updated_time.timestamp_millis())
+                })
+                .collect();
+            (missing, state.generation)
+        };
+        let mut loaded: HashMap<String, (i64, NoteMeta)> = HashMap::with_capacity(missing.len());
+        for abbr in missing {
+            let note = load_note(&abbr.id)?;
+            let time = abbr.user_updated_time.timestamp_millis();
+            loaded.insert(abbr.id.clone(), (time, NoteMeta::new(&note)));
+        }
+        let mut state = self.lock();
+        let metas: Option<Vec<NoteMeta>> = notes
+            .iter()
+            .map(|note| {
+                loaded
+                    .get(&note.id)
+                    .or_else(|| state.entries.get(&note.id))
+                    .map(|(_, meta)| *meta)
+            })
+            .collect();
+        if state.generation == generation {
+            state.entries.extend(loaded);
+        }
+        drop(state);
+        match metas {
+            Some(metas) => Ok(metas),
+            // A write invalidated a cached note in the meantime.
+            None => self.get(notes, load_note),
+        }
+    }
+
+    pub fn invalidate(&self, ids: &[&str]) {
+        let mut state = self.lock();
+        for id in ids {
+            state.entries.remove(*id);
+        }
+        state.generation += 1;
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.generation += 1;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        // The entries are always valid, even if a thread panicked while holding the lock.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use ruslin_data::DateTimeTimestamp;
+
+    use super::*;
+
+    fn abbr(note: &Note, user_updated_time: i64) -> AbbrNote {
+        AbbrNote {
+            id: note.id.clone(),
+            parent_id: None,
+            title: note.title.clone(),
+            user_created_time: DateTimeTimestamp::from_timestamp_millis(0),
+            user_updated_time: DateTimeTimestamp::from_timestamp_millis(user_updated_time),
+        }
+    }
+
+    #[test]
+    fn invalidation() {
+        let cache = NoteMetaCache::default();
+        let mut note = Note::new(None, "Note".to_string(), String::new());
+        let loads = Cell::new(0);
+        let get = |note: &Note, time: i64| {
+            let metas = cache
+                .get(&[abbr(note, time)], |_| {
+                    loads.set(loads.get() + 1);
+                    Ok(note.clone())
+                })
+                .unwrap();
+            metas[0]
+        };
+
+        assert!(!get(&note, 1).is_pinned);
+        assert!(!get(&note, 1).is_pinned);
+        assert_eq!(loads.get(), 1);
+
+        // A change that keeps `user_updated_time` is served from the cache until invalidated.
+        note.application_data = pin::set_pinned("", true).unwrap();
+        assert!(!get(&note, 1).is_pinned);
+        cache.invalidate(&[&note.id]);
+        assert!(get(&note, 1).is_pinned);
+        assert_eq!(loads.get(), 2);
+
+        note.is_todo = true;
+        assert!(get(&note, 2).is_todo);
+        assert_eq!(loads.get(), 3);
+
+        cache.clear();
+        get(&note, 2);
+        assert_eq!(loads.get(), 4);
+    }
+
+    #[test]
+    fn invalidation_while_loading() {
+        let cache = NoteMetaCache::default();
+        let note = Note::new(None, "Note".to_string(), String::new());
+        let notes = [abbr(&note, 1)];
+        cache
+            .get(&notes, |id| {
+                // A write of the note while it's loaded.
+                cache.invalidate(&[id]);
+                Ok(note.clone())
+            })
+            .unwrap();
+        let loads = Cell::new(0);
+        cache
+            .get(&notes, |_| {
+                loads.set(loads.get() + 1);
+                Ok(note.clone())
+            })
+            .unwrap();
+        assert_eq!(loads.get(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::math::MaskedText;
+use crate::utf16::utf8_to_utf16_offsets;
+
+pub struct FFIOutlineHeading {
+    pub level: i32,
+    /// Plain text of the heading, markdown syntax removed.
+    pub text: String,
+    /// UTF-16 offset of the heading in the source.
+    pub offset: i32,
+    /// Anchor id, the same one the preview writes on the heading.
+    pub id: String,
+    pub children: Vec<FFIOutlineHeading>,
+}
+
+pub struct Heading {
+    pub level: i32,
+    pub text: String,
+    pub offset: usize,
+    pub id: String,
+}
+
+/// Returns all headings in document order. Headings without an explicit `{#id}` get a
+/// GitHub-style slug, made unique with a `-1`, `-2`, ... suffix. `masked` is the masked
+/// `text`, so that callers parsing it too only mask it once.
+pub fn headings(text: &str, masked: &MaskedText) -> Vec<Heading> {
+    let utf16_offsets = utf8_to_utf16_offsets(text);
+    let mut headings: Vec<Heading> = Vec::new();
+    let mut current: Option<(i32, Option<String>, usize, String)> = None;
+    let mut slug_counts: HashMap<String, usize> = HashMap::new();
+    for (event, range) in Parser::new_ext(&masked.text, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(level, id, _)) => {
+                current = Some((
+                    level as i32,
+                    id.map(|id| id.to_string()),
+                    utf16_offsets[range.start],
+                    String::new(),
+                ));
+            }
+            Event::Text(s) | Event::Code(s) => {
+                if let Some((_, _, _, heading_text)) = current.as_mut() {
+                    match masked.span(&range) {
+                        Some(span) => heading_text.push_str(span.tex(text)),
+                        None => heading_text.push_str(&s),
+                    }
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, _, _, heading_text)) = current.as_mut() {
+                    heading_text.push(' ');
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                let Some((level, id, offset, heading_text)) = current.take() else {
+                    continue;
+                };
+                let heading_text = heading_text.trim().to_string();
+                let id = match id {
+                    Some(id) => {
+                        *slug_counts.entry(id.clone()).or_insert(0) += 1;
+                        id
+                    }
+                    None => {
+                        let slug = slugify(&heading_text);
+                        let count = slug_counts.entry(slug.clone()).or_insert(0);
+                        let id = if *count == 0 {
+                            slug
+                        } else {
+                            format!("{slug}-{count}")
+                        };
+                        *count += 1;
+                        id
+                    }
+                };
+                headings.push(Heading {
+                    level,
+                    text: heading_text,
+                    offset,
+                    id,
+                });
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+fn slugify(text: &str) -> String {
+    let slug: String = text
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c)
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect();
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug
+    }
+}
+
+pub fn extract_outline(text: &str) -> Vec<FFIOutlineHeading> {
+    outline(&headings(text, &MaskedText::new(text)))
+}
+
+pub fn outline(headings: &[Heading]) -> Vec<FFIOutlineHeading> {
+    let mut index = 0;
+    nest(headings, &mut index, 0)
+}
+
+fn nest(headings: &[Heading], index: &mut usize, parent_level: i32) -> Vec<FFIOutlineHeading> {
+    let mut outline: Vec<FFIOutlineHeading> = Vec::new();
+    while let Some(heading) = headings.get(*index) {
+        if heading.level <= parent_level {
+            break;
+        }
+        *index += 1;
+        let children = nest(headings, index, heading.level);
+        outline.push(FFIOutlineHeading {
+            level: heading.level,
+            text: heading.text.clone(),
+            offset: heading.offset as i32,
+            id: heading.id.clone(),
+            children,
+        });
+    }
+    outline
+}
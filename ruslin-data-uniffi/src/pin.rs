@@ -0,0 +1,79 @@
+use serde_json::{Map, Value};
+
+/// The pin state is kept in `application_data`, so it's synced with the note. The data is a
+/// JSON object, other apps may add their own keys next to this one.
+const APPLICATION_DATA_KEY: &str = "org.dianqk.ruslin";
+const PINNED_KEY: &str = "pinned";
+
+fn parse(application_data: &str) -> Option<Map<String, Value>> {
+    if application_data.trim().is_empty() {
+        return Some(Map::new());
+    }
+    match serde_json::from_str(application_data) {
+        Ok(Value::Object(data)) => Some(data),
+        _ => None,
+    }
+}
+
+pub fn is_pinned(application_data: &str) -> bool {
+    parse(application_data)
+        .and_then(|data| data.get(APPLICATION_DATA_KEY)?.get(PINNED_KEY)?.as_bool())
+        .unwrap_or_default()
+}
+
+/// Returns the application data with the pin state set, keeping everything else. Returns
+/// `None` if the data isn't a JSON object, which would be lost.
+pub fn set_pinned(application_data: &str, pinned: bool) -> Option<String> {
+    let mut data = parse(application_data)?;
+    let app_data = data
+        .entry(APPLICATION_DATA_KEY)
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()?;
+    if pinned {
+        app_data.insert(PINNED_KEY.to_string(), Value::Bool(true));
+    } else {
+        app_data.remove(PINNED_KEY);
+        if app_data.is_empty() {
+            data.remove(APPLICATION_DATA_KEY);
+        }
+    }
+    if data.is_empty() {
+        return Some(String::new());
+    }
+    serde_json::to_string(&data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let pinned = set_pinned("", true).unwrap();
+        assert_eq!(pinned, r#"{"org.dianqk.ruslin":{"pinned":true}}"#);
+        assert!(is_pinned(&pinned));
+        assert_eq!(set_pinned(&pinned, false).unwrap(), "");
+        assert!(!is_pinned(""));
+    }
+
+    #[test]
+    fn foreign_keys() {
+        let data = r#"{"other.app":{"a":[1,2]},"org.dianqk.ruslin":{"color":"red"}}"#;
+        let pinned = set_pinned(data, true).unwrap();
+        assert!(is_pinned(&pinned));
+        let unpinned = set_pinned(&pinned, false).unwrap();
+        assert!(!is_pinned(&unpinned));
+        let parsed: Value = serde_json::from_str(&unpinned).unwrap();
+        assert_eq!(parsed, serde_json::from_str::<Value>(data).unwrap());
+    }
+
+    #[test]
+    fn malformed() {
+        for data in ["not json", "[]", "1", r#"{"org.dianqk.ruslin":true}"#] {
+            assert!(!is_pinned(data), "{data}");
+            assert_eq!(set_pinned(data, true), None, "{data}");
+        }
+        assert!(!is_pinned(r#"{"org.dianqk.ruslin":{"pinned":"yes"}}"#));
+        assert!(is_pinned(" {\"org.dianqk.ruslin\": {\"pinned\": true}} "));
+    }
+}
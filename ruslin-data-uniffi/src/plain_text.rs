@@ -0,0 +1,350 @@
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag};
+use ruslin_data::RuslinData;
+
+use crate::math::MaskedText;
+
+/// Returns the first `max_chars` characters of the text the reader sees, with markdown
+/// syntax removed and all whitespace collapsed into single spaces.
+pub fn excerpt(text: &str, max_chars: usize) -> String {
+    let mut excerpt = String::new();
+    let mut chars: usize = 0;
+    let mut pending_space = false;
+    let mut image_depth: usize = 0;
+    for event in Parser::new_ext(text, Options::all()) {
+        let text = match event {
+            Event::Start(Tag::Image(..)) => {
+                image_depth += 1;
+                continue;
+            }
+            Event::End(Tag::Image(..)) => {
+                image_depth -= 1;
+                continue;
+            }
+            Event::Text(text) | Event::Code(text) if image_depth == 0 => text,
+            Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..)) => {
+                continue;
+            }
+            Event::SoftBreak | Event::HardBreak | Event::End(_) | Event::Rule => {
+                pending_space = true;
+                continue;
+            }
+            _ => continue,
+        };
+        for c in text.chars() {
+            if c.is_whitespace() {
+                pending_space = true;
+                continue;
+            }
+            if pending_space && !excerpt.is_empty() {
+                if chars == max_chars {
+                    return excerpt;
+                }
+                excerpt.push(' ');
+                chars += 1;
+            }
+            pending_space = false;
+            if chars == max_chars {
+                return excerpt;
+            }
+            excerpt.push(c);
+            chars += 1;
+        }
+    }
+    excerpt
+}
+
+pub struct FFIPlainTextOptions {
+    /// Appends the URL of a link after its text.
+    pub link_urls: bool,
+    /// Keeps the bullets, numbers and task markers of list items.
+    pub list_bullets: bool,
+    /// Aligns the table columns, otherwise cells are separated by tabs.
+    pub table_layout: bool,
+    /// Keeps the content of code blocks. Inline code is always kept.
+    pub code: bool,
+}
+
+/// Converts markdown to the text the reader sees. Links and images of resources are replaced
+/// by the resource titles.
+pub fn markdown_to_plain_text(
+    data: &RuslinData,
+    text: &str,
+    options: &FFIPlainTextOptions,
+) -> String {
+    let masked = MaskedText::new(text);
+    let mut writer = PlainTextWriter {
+        data,
+        options,
+        output: String::with_capacity(text.len()),
+        pending_newlines: 0,
+        lists: Vec::new(),
+        links: Vec::new(),
+        skip_depth: 0,
+        table: None,
+    };
+    for (event, range) in Parser::new_ext(&masked.text, Options::all()).into_offset_iter() {
+        let event = match (event, masked.span(&range)) {
+            (Event::Code(_), Some(span)) => Event::Code(span.tex(text).into()),
+            (event, _) => event,
+        };
+        writer.event(event);
+    }
+    writer.output
+}
+
+struct Table {
+    rows: Vec<Vec<String>>,
+    cell: Option<String>,
+}
+
+struct PlainTextWriter<'a> {
+    data: &'a RuslinData,
+    options: &'a FFIPlainTextOptions,
+    output: String,
+    /// Line breaks to write before the next text, to separate blocks.
+    pending_newlines: usize,
+    /// The next number of each open list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// The destination of each open link, if it's written after the link text.
+    links: Vec<Option<String>>,
+    /// Nesting depth of the skipped events, like the text of a resource link.
+    skip_depth: usize,
+    table: Option<Table>,
+}
+
+impl<'a> PlainTextWriter<'a> {
+    fn event(&mut self, event: Event<'_>) {
+        if self.skip_depth > 0 {
+            match event {
+                Event::Start(_) => self.skip_depth += 1,
+                Event::End(_) => self.skip_depth -= 1,
+                _ => {}
+            }
+            // The end of the skipped element is skipped too, as its start was.
+            return;
+        }
+        match event {
+            Event::Start(tag) => self.start_tag(tag),
+            Event::End(tag) => self.end_tag(tag),
+            Event::Text(text) | Event::Code(text) => self.write(&text),
+            Event::SoftBreak => self.write(" "),
+            Event::HardBreak => self.write("\n"),
+            Event::TaskListMarker(checked) => {
+                if self.options.list_bullets {
+                    self.write(if checked { "[x] " } else { "[ ] " });
+                }
+            }
+            Event::Rule => self.end_block(2),
+            Event::Html(_) | Event::FootnoteReference(_) => {}
+        }
+    }
+
+    fn start_tag(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.end_block(2);
+                } else {
+                    self.end_block(1);
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.end_block(1);
+                if !self.options.list_bullets {
+                    return;
+                }
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{indent}{}. ", *number - 1)
+                    }
+                    _ => format!("{indent}- "),
+                };
+                self.write(&bullet);
+            }
+            Tag::CodeBlock(_) if !self.options.code => self.skip_depth = 1,
+            Tag::Link(link_type, dest, _) | Tag::Image(link_type, dest, _) => {
+                if let Some(title) = self.resource_title(&dest) {
+                    self.write(&title);
+                    self.skip_depth = 1;
+                    return;
+                }
+                // Autolinks already show their URL.
+                let show_url = self.options.link_urls
+                    && !dest.is_empty()
+                    && !matches!(link_type, LinkType::Autolink | LinkType::Email);
+                self.links.push(show_url.then(|| dest.to_string()));
+            }
+            Tag::Table(_) => {
+                self.end_block(2);
+                self.table = Some(Table {
+                    rows: Vec::new(),
+                    cell: None,
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(table) = &mut self.table {
+                    table.cell = Some(String::new());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_tag(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph | Tag::Heading(..) | Tag::BlockQuote | Tag::FootnoteDefinition(_) => {
+                let newlines = if self.lists.is_empty() { 2 } else { 1 };
+                self.end_block(newlines);
+            }
+            Tag::CodeBlock(_) => {
+                // The last line break of the code is part of the block.
+                if self.output.ends_with('\n') {
+                    self.output.pop();
+                }
+                self.end_block(2);
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+                self.end_block(if self.lists.is_empty() { 2 } else { 1 });
+            }
+            Tag::Item => self.end_block(1),
+            Tag::Link(..) | Tag::Image(..) => {
+                if let Some(Some(url)) = self.links.pop() {
+                    self.write(&format!(" ({url})"));
+                }
+            }
+            Tag::TableCell => {
+                if let Some(table) = &mut self.table {
+                    let cell = table.cell.take().unwrap_or_default();
+                    if let Some(row) = table.rows.last_mut() {
+                        row.push(cell.trim().to_string());
+                    }
+                }
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    let text = self.layout_table(&table.rows);
+                    self.write(&text);
+                }
+                self.end_block(2);
+            }
+            _ => {}
+        }
+    }
+
+    /// Ends the current block, separated from the next text by `newlines` line breaks.
+    fn end_block(&mut self, newlines: usize) {
+        self.pending_newlines = self.pending_newlines.max(newlines);
+    }
+
+    fn write(&mut self, text: &str) {
+        if let Some(cell) = self.table.as_mut().and_then(|table| table.cell.as_mut()) {
+            cell.push_str(text);
+            return;
+        }
+        if !self.output.is_empty() {
+            for _ in 0..self.pending_newlines {
+                self.output.push('\n');
+            }
+        }
+        self.pending_newlines = 0;
+        self.output.push_str(text);
+    }
+
+    fn layout_table(&self, rows: &[Vec<String>]) -> String {
+        if !self.options.table_layout {
+            let rows: Vec<String> = rows.iter().map(|row| row.join("\t")).collect();
+            return rows.join("\n");
+        }
+        let mut widths: Vec<usize> = Vec::new();
+        for row in rows {
+            for (index, cell) in row.iter().enumerate() {
+                let width = cell.chars().count();
+                match widths.get_mut(index) {
+                    Some(column_width) => *column_width = (*column_width).max(width),
+                    None => widths.push(width),
+                }
+            }
+        }
+        let mut lines: Vec<String> = Vec::with_capacity(rows.len() + 1);
+        for (index, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            lines.push(cells.join(" | ").trim_end().to_string());
+            // Separates the head from the body.
+            if index == 0 {
+                let separator: Vec<String> =
+                    widths.iter().map(|width| "-".repeat(*width)).collect();
+                lines.push(separator.join("-|-"));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Returns the title of the resource a link destination like `:/id` points to.
+    fn resource_title(&self, dest: &str) -> Option<String> {
+        let id = dest.strip_prefix(":/")?;
+        if id.len() != 32 {
+            return None;
+        }
+        let resource = self.data.db.load_resource(id).ok()?;
+        if resource.title.is_empty() {
+            Some(resource.filename)
+        } else {
+            Some(resource.title)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruslin_data::{Resource, UpdateSource};
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const OPTIONS: FFIPlainTextOptions = FFIPlainTextOptions {
+        link_urls: true,
+        list_bullets: true,
+        table_layout: true,
+        code: false,
+    };
+
+    #[test]
+    fn resource_image_in_link() {
+        let dir = TempDir::new("plain-text-image");
+        let data = dir.data();
+        let resource = Resource::new(
+            "Photo".to_string(),
+            "image/png".to_string(),
+            "png".to_string(),
+            1,
+        );
+        data.db
+            .replace_resource(&resource, UpdateSource::LocalEdit)
+            .unwrap();
+        let text = format!("[![](:/{}) caption](https://example.com)", resource.id);
+        let plain_text = markdown_to_plain_text(&data, &text, &OPTIONS);
+        assert_eq!(plain_text, "Photo caption (https://example.com)");
+    }
+
+    #[test]
+    fn skipped_code_block() {
+        let dir = TempDir::new("plain-text-code");
+        let data = dir.data();
+        let text = "Before\n\n```\ncode\n```\n\nAfter\n\n- a\n  ```\n  code\n  ```\n- b\n";
+        let plain_text = markdown_to_plain_text(&data, text, &OPTIONS);
+        assert_eq!(plain_text, "Before\n\nAfter\n\n- a\n- b");
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use serde_json::{Map, Value};
+
+const FILE_NAME: &str = "recent_notes.json";
+const MAX_ENTRIES: usize = 200;
+/// How many places the most recently opened note moves up in search results.
+const RECENCY_BOOST: f64 = 3.0;
+
+#[derive(Clone)]
+pub struct RecentNote {
+    pub id: String,
+    pub opened_time: i64,
+}
+
+/// The notes opened on this device, most recent first. The history is kept in a file of the
+/// data directory instead of the database, so it's never synced. It's only a convenience,
+/// so a file that can't be read or written is logged and otherwise ignored.
+pub struct RecentNotes {
+    path: PathBuf,
+    entries: Mutex<Vec<RecentNote>>,
+}
+
+impl RecentNotes {
+    pub fn new(data_dir: &Path) -> Self {
+        let path = data_dir.join(FILE_NAME);
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => parse(&content).unwrap_or_else(|| {
+                log::warn!("invalid recent notes in {}", path.display());
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                log::warn!("failed to read {}: {e}", path.display());
+                Vec::new()
+            }
+        };
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    pub fn entries(&self) -> Vec<RecentNote> {
+        self.lock().clone()
+    }
+
+    pub fn mark_opened(&self, id: &str, opened_time: i64) {
+        let mut entries = self.lock();
+        entries.retain(|entry| entry.id != id);
+        entries.insert(
+            0,
+            RecentNote {
+                id: id.to_string(),
+                opened_time,
+            },
+        );
+        entries.truncate(MAX_ENTRIES);
+        self.save(&entries);
+    }
+
+    pub fn remove(&self, ids: &[&str]) {
+        let mut entries = self.lock();
+        let count = entries.len();
+        entries.retain(|entry| !ids.contains(&entry.id.as_str()));
+        if entries.len() != count {
+            self.save(&entries);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        entries.clear();
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to remove {}: {e}", self.path.display());
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<RecentNote>> {
+        // The entries are always valid, even if a thread panicked while holding the lock.
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Writes to a temporary file first, so a crash can't leave a truncated history.
+    fn save(&self, entries: &[RecentNote]) {
+        let entries: Vec<Value> = entries
+            .iter()
+            .map(|entry| {
+                let mut object = Map::new();
+                object.insert("id".to_string(), Value::from(entry.id.as_str()));
+                object.insert("opened_time".to_string(), Value::from(entry.opened_time));
+                Value::Object(object)
+            })
+            .collect();
+        let temp_path = self.path.with_extension("json.tmp");
+        let result = std::fs::write(&temp_path, Value::Array(entries).to_string())
+            .and_then(|_| std::fs::rename(&temp_path, &self.path));
+        if let Err(e) = result {
+            log::warn!("failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+fn parse(content: &str) -> Option<Vec<RecentNote>> {
+    let Value::Array(values) = serde_json::from_str(content).ok()? else {
+        return None;
+    };
+    let entries = values
+        .iter()
+        .filter_map(|value| {
+            Some(RecentNote {
+                id: value.get("id")?.as_str()?.to_string(),
+                opened_time: value.get("opened_time")?.as_i64()?,
+            })
+        })
+        .collect();
+    Some(entries)
+}
+
+/// Moves recently opened notes a few places up in results ranked by relevance. The boost
+/// fades with the place in the history, so relevance still decides the order overall.
+pub fn rank_by_recency<T>(items: Vec<T>, id: impl Fn(&T) -> &str, recent: &[RecentNote]) -> Vec<T> {
+    if recent.is_empty() {
+        return items;
+    }
+    let mut ranked: Vec<(f64, T)> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let boost = recent
+                .iter()
+                .position(|entry| entry.id == id(&item))
+                .map(|place| RECENCY_BOOST * (1.0 - place as f64 / recent.len() as f64))
+                .unwrap_or_default();
+            (index as f64 - boost, item)
+        })
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    ranked.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn ids(recent: &RecentNotes) -> Vec<String> {
+        recent.entries().into_iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn mark_opened() {
+        let dir = TempDir::new("recent-mark-opened");
+        let recent = RecentNotes::new(&dir.path);
+        recent.mark_opened("a", 1);
+        recent.mark_opened("b", 2);
+        recent.mark_opened("a", 3);
+        assert_eq!(ids(&recent), ["a", "b"]);
+        assert_eq!(recent.entries()[0].opened_time, 3);
+
+        // The history is kept across restarts.
+        let recent = RecentNotes::new(&dir.path);
+        assert_eq!(ids(&recent), ["a", "b"]);
+        recent.remove(&["a", "c"]);
+        assert_eq!(ids(&RecentNotes::new(&dir.path)), ["b"]);
+        recent.clear();
+        assert!(RecentNotes::new(&dir.path).entries().is_empty());
+    }
+
+    #[test]
+    fn max_entries() {
+        let dir = TempDir::new("recent-max-entries");
+        let recent = RecentNotes::new(&dir.path);
+        for index in 0..MAX_ENTRIES + 10 {
+            recent.mark_opened(&index.to_string(), index as i64);
+        }
+        let entries = recent.entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].id, (MAX_ENTRIES + 9).to_string());
+        assert_eq!(entries[MAX_ENTRIES - 1].id, "10");
+    }
+
+    #[test]
+    fn corrupt_file() {
+        let dir = TempDir::new("recent-corrupt-file");
+        let path = dir.path.join(FILE_NAME);
+        for content in ["", "{", r#"{"id":"a"}"#, "null"] {
+            std::fs::write(&path, content).unwrap();
+            assert!(
+                RecentNotes::new(&dir.path).entries().is_empty(),
+                "{content}"
+            );
+        }
+        // Invalid entries are skipped.
+        std::fs::write(
+            &path,
+            r#"[{"id":"a","opened_time":1},{"id":2},"b",{"id":"c","opened_time":3}]"#,
+        )
+        .unwrap();
+        assert_eq!(ids(&RecentNotes::new(&dir.path)), ["a", "c"]);
+
+        std::fs::write(&path, "not json").unwrap();
+        let recent = RecentNotes::new(&dir.path);
+        recent.mark_opened("a", 1);
+        assert_eq!(ids(&RecentNotes::new(&dir.path)), ["a"]);
+    }
+
+    #[test]
+    fn recency_ranking() {
+        let recent: Vec<RecentNote> = ["f", "c"]
+            .into_iter()
+            .map(|id| RecentNote {
+                id: id.to_string(),
+                opened_time: 0,
+            })
+            .collect();
+        let rank = |recent: &[RecentNote]| {
+            rank_by_recency(vec!["a", "b", "c", "d", "e", "f"], |id| id, recent)
+        };
+        assert_eq!(rank(&[]), ["a", "b", "c", "d", "e", "f"]);
+        // "f" moves up by the full boost, "c" by half of it.
+        assert_eq!(rank(&recent), ["a", "c", "b", "f", "d", "e"]);
+    }
+}
@@ -0,0 +1,168 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::math::MaskedText;
+
+/// Words per minute used to estimate the reading time.
+const READING_WORDS_PER_MINUTE: f64 = 200.0;
+/// CJK characters per minute used to estimate the reading time. A CJK character counts as a
+/// word, but most words take more than one, so they're read faster than words.
+const READING_CJK_CHARACTERS_PER_MINUTE: f64 = 400.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct FFINoteStatistics {
+    /// Every CJK character counts as a word.
+    pub words: i64,
+    pub characters: i64,
+    pub characters_without_spaces: i64,
+    pub lines: i64,
+    pub paragraphs: i64,
+    pub headings: i64,
+    pub links: i64,
+    pub images: i64,
+    pub reading_time_minutes: i64,
+}
+
+/// Chinese and Japanese don't put spaces between words. Like most word counters, every
+/// ideograph and kana counts as a word there, instead of segmenting with a dictionary.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Counts the runs between spaces that contain a letter or digit, and the CJK characters.
+fn count_words(text: &str) -> (i64, i64) {
+    let mut words = 0;
+    let mut cjk_characters = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            cjk_characters += 1;
+            words += in_word as i64;
+            in_word = false;
+        } else if c.is_whitespace() {
+            words += in_word as i64;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            in_word = true;
+        }
+    }
+    (words + in_word as i64, cjk_characters)
+}
+
+/// Counts what the reader of the rendered note sees, without the markdown syntax. Image
+/// descriptions aren't visible, so they aren't counted.
+pub fn note_statistics(text: &str) -> FFINoteStatistics {
+    let mut statistics = FFINoteStatistics {
+        lines: text.lines().count() as i64,
+        ..Default::default()
+    };
+    let masked = MaskedText::new(text);
+    let mut cjk_characters = 0;
+    let mut image_depth: usize = 0;
+    // Consecutive text events, e.g. split at escapes, are counted as one run of words.
+    let mut run = String::new();
+    for (event, range) in Parser::new_ext(&masked.text, Options::all()).into_offset_iter() {
+        match event {
+            Event::Text(text) if image_depth == 0 => {
+                run.push_str(&text);
+                continue;
+            }
+            Event::Code(code) if image_depth == 0 => {
+                match masked.span(&range) {
+                    Some(span) => run.push_str(span.tex(text)),
+                    None => run.push_str(&code),
+                }
+                continue;
+            }
+            Event::SoftBreak if image_depth == 0 => {
+                run.push(' ');
+                continue;
+            }
+            Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..))
+            | Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..)) => {
+                if let Event::Start(Tag::Link(..)) = event {
+                    statistics.links += 1;
+                }
+                continue;
+            }
+            Event::Start(Tag::Paragraph) => statistics.paragraphs += 1,
+            Event::Start(Tag::Heading(..)) => statistics.headings += 1,
+            Event::Start(Tag::Image(..)) => {
+                statistics.images += 1;
+                image_depth += 1;
+            }
+            Event::End(Tag::Image(..)) => image_depth -= 1,
+            _ => {}
+        }
+        cjk_characters += add_run(&mut statistics, &run);
+        run.clear();
+    }
+    cjk_characters += add_run(&mut statistics, &run);
+
+    let other_words = statistics.words - cjk_characters;
+    statistics.reading_time_minutes = (other_words as f64 / READING_WORDS_PER_MINUTE
+        + cjk_characters as f64 / READING_CJK_CHARACTERS_PER_MINUTE)
+        .ceil() as i64;
+    statistics
+}
+
+/// Adds the counts of a run of text, returning the number of CJK characters in it.
+fn add_run(statistics: &mut FFINoteStatistics, run: &str) -> i64 {
+    let (words, cjk_characters) = count_words(run);
+    statistics.words += words + cjk_characters;
+    for c in run.chars() {
+        statistics.characters += 1;
+        if !c.is_whitespace() {
+            statistics.characters_without_spaces += 1;
+        }
+    }
+    cjk_characters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words() {
+        assert_eq!(count_words("Hello, world! -- 42"), (3, 0));
+        assert_eq!(count_words("中文字数"), (0, 4));
+        assert_eq!(count_words("用Rust写的app"), (2, 3));
+        assert_eq!(count_words("ひらがなとカタカナ"), (0, 9));
+        assert_eq!(count_words("한국어 단어"), (2, 0));
+    }
+
+    #[test]
+    fn reading_time() {
+        assert_eq!(note_statistics("").reading_time_minutes, 0);
+        assert_eq!(note_statistics("word").reading_time_minutes, 1);
+        assert_eq!(
+            note_statistics(&"word ".repeat(200)).reading_time_minutes,
+            1
+        );
+        assert_eq!(
+            note_statistics(&"word ".repeat(201)).reading_time_minutes,
+            2
+        );
+    }
+
+    #[test]
+    fn cjk_reading_time() {
+        let statistics = note_statistics(&"中文".repeat(200));
+        assert_eq!(statistics.words, 400);
+        assert_eq!(statistics.characters, 400);
+        assert_eq!(statistics.reading_time_minutes, 1);
+        let statistics =
+            note_statistics(&format!("{}\n\n{}", "中".repeat(400), "word ".repeat(100)));
+        assert_eq!(statistics.words, 500);
+        assert_eq!(statistics.reading_time_minutes, 2);
+        assert_eq!(note_statistics(&"中".repeat(401)).reading_time_minutes, 2);
+    }
+}
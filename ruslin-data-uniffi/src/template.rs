@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use ruslin_data::Folder;
+
+/// Notes in a top-level folder with this title, or in its subfolders, are templates.
+pub const TEMPLATES_FOLDER_TITLE: &str = "Templates";
+
+pub struct FFITemplate {
+    pub id: String,
+    pub title: String,
+    /// The variables the user is asked for, i.e. all but the built-in ones.
+    pub variables: Vec<String>,
+}
+
+/// Returns the ids of the templates folders and their subfolders.
+pub fn template_folder_ids(folders: &[Folder]) -> Vec<&str> {
+    let mut ids: Vec<&str> = folders
+        .iter()
+        .filter(|folder| folder.parent_id.as_deref().unwrap_or_default().is_empty())
+        .filter(|folder| {
+            folder
+                .title
+                .trim()
+                .eq_ignore_ascii_case(TEMPLATES_FOLDER_TITLE)
+        })
+        .map(|folder| folder.id.as_str())
+        .collect();
+    let mut index = 0;
+    while index < ids.len() {
+        let parent_id = ids[index];
+        for folder in folders {
+            if folder.parent_id.as_deref() == Some(parent_id) && !ids.contains(&folder.id.as_str())
+            {
+                ids.push(&folder.id);
+            }
+        }
+        index += 1;
+    }
+    ids
+}
+
+const BUILT_IN_VARIABLES: &[&str] = &["date", "time", "datetime", "title", "folder"];
+
+pub struct TemplateContext<'a> {
+    pub now: DateTime<Local>,
+    pub title: &'a str,
+    pub folder: &'a str,
+    /// Custom variables, which may also override the built-in ones.
+    pub variables: &'a HashMap<String, String>,
+}
+
+/// A placeholder, `{{name}}` or `{{name:format}}`.
+struct Placeholder<'a> {
+    range: std::ops::Range<usize>,
+    name: &'a str,
+    format: Option<&'a str>,
+}
+
+/// Finds the placeholders of the template. Anything else between braces, and braces escaped
+/// as `\{{`, is plain text.
+fn placeholders(template: &str) -> Vec<Placeholder<'_>> {
+    let mut placeholders = Vec::new();
+    let mut index = 0;
+    while let Some(offset) = template[index..].find("{{") {
+        let start = index + offset;
+        index = start + 2;
+        if template[..start].ends_with('\\') {
+            continue;
+        }
+        let Some(length) = template[index..].find("}}") else {
+            break;
+        };
+        let content = template[index..index + length].trim();
+        let (name, format) = match content.split_once(':') {
+            Some((name, format)) => (name.trim(), Some(format)),
+            None => (content, None),
+        };
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if is_name {
+            index += length + 2;
+            placeholders.push(Placeholder {
+                range: start..index,
+                name,
+                format,
+            });
+        }
+    }
+    placeholders
+}
+
+/// Returns the custom variables of the template in the order they first appear.
+pub fn variables(template: &str) -> Vec<String> {
+    let mut variables: Vec<String> = Vec::new();
+    for placeholder in placeholders(template) {
+        let name = placeholder.name;
+        if !BUILT_IN_VARIABLES.contains(&name) && !variables.iter().any(|v| v == name) {
+            variables.push(name.to_string());
+        }
+    }
+    variables
+}
+
+/// Replaces the placeholders with their values. Values are inserted as is and never expanded
+/// themselves, and unknown variables are kept, so nothing in a template can go wrong.
+pub fn expand(template: &str, context: &TemplateContext) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut index = 0;
+    for placeholder in placeholders(template) {
+        let literal = &template[index..placeholder.range.start];
+        // Drops the backslash of escaped braces.
+        expanded.push_str(&literal.replace("\\{{", "{{"));
+        index = placeholder.range.end;
+        match value(&placeholder, context) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&template[placeholder.range]),
+        }
+    }
+    expanded.push_str(&template[index..].replace("\\{{", "{{"));
+    expanded
+}
+
+fn value(placeholder: &Placeholder, context: &TemplateContext) -> Option<String> {
+    if let Some(value) = context.variables.get(placeholder.name) {
+        return Some(value.clone());
+    }
+    let default_format = match placeholder.name {
+        "title" => return Some(context.title.to_string()),
+        "folder" => return Some(context.folder.to_string()),
+        "date" => "%Y-%m-%d",
+        "time" => "%H:%M",
+        "datetime" => "%Y-%m-%d %H:%M",
+        _ => return None,
+    };
+    // Dates take a strftime format, e.g. `{{date:%d/%m/%Y}}`.
+    let format = placeholder.format.unwrap_or(default_format);
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+    Some(context.now.format_with_items(items.into_iter()).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn expand_with(template: &str, variables: &[(&str, &str)]) -> String {
+        let variables: HashMap<String, String> = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let context = TemplateContext {
+            now: Local.with_ymd_and_hms(2024, 3, 5, 14, 7, 9).unwrap(),
+            title: "Title",
+            folder: "Folder",
+            variables: &variables,
+        };
+        expand(template, &context)
+    }
+
+    #[test]
+    fn built_in_variables() {
+        assert_eq!(
+            expand_with("{{title}} in {{ folder }}: {{date}} {{time}}", &[]),
+            "Title in Folder: 2024-03-05 14:07"
+        );
+        assert_eq!(expand_with("{{datetime}}", &[]), "2024-03-05 14:07");
+        assert_eq!(expand_with("{{title}}", &[("title", "Custom")]), "Custom");
+    }
+
+    #[test]
+    fn date_formats() {
+        assert_eq!(expand_with("{{date:%d/%m/%Y}}", &[]), "05/03/2024");
+        assert_eq!(expand_with("{{time:%H:%M:%S}}", &[]), "14:07:09");
+        assert_eq!(
+            expand_with("{{ datetime:%A %B %-d }}", &[]),
+            "Tuesday March 5"
+        );
+        assert_eq!(expand_with("{{date:%Q}}", &[]), "{{date:%Q}}");
+        assert_eq!(expand_with("{{title:%Y}}", &[]), "Title");
+    }
+
+    #[test]
+    fn custom_variables() {
+        let template = "{{name}} {{ name }} {{other}} {{title}}";
+        assert_eq!(variables(template), ["name", "other"]);
+        assert_eq!(
+            expand_with(template, &[("name", "{{other}}")]),
+            "{{other}} {{other}} {{other}} Title"
+        );
+    }
+
+    #[test]
+    fn unknown_variables() {
+        assert_eq!(
+            expand_with("{{unknown}} {{date}}", &[]),
+            "{{unknown}} 2024-03-05"
+        );
+        assert_eq!(
+            expand_with("{{not a name}} {{}}", &[]),
+            "{{not a name}} {{}}"
+        );
+        assert_eq!(expand_with("{ {{title}} }", &[]), "{ Title }");
+        assert_eq!(expand_with("{{{title}}}", &[]), "{{{title}}}");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(expand_with("\\{{title}} {{title}}", &[]), "{{title}} Title");
+        assert_eq!(expand_with("\\{{ \\{{x}}", &[]), "{{ {{x}}");
+        assert!(variables("\\{{name}}").is_empty());
+    }
+
+    #[test]
+    fn unclosed_placeholders() {
+        assert_eq!(expand_with("{{title}} {{date", &[]), "Title {{date");
+        assert_eq!(expand_with("{{title", &[]), "{{title");
+        assert_eq!(expand_with("{{ {{title}}", &[]), "{{ Title");
+        assert!(variables("{{name").is_empty());
+    }
+}
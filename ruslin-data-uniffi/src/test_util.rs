@@ -0,0 +1,87 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+};
+
+use ruslin_data::RuslinData;
+
+/// A new temporary directory, which is removed on drop.
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ruslin-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// A database in the directory, which is also the resource directory.
+    pub fn data(&self) -> RuslinData {
+        RuslinData::new(&self.path, &self.path).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+pub struct Response {
+    pub path: &'static str,
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    /// Sends `Content-Length`, otherwise the body ends when the connection is closed.
+    pub content_length: bool,
+}
+
+/// Serves the responses on 127.0.0.1 by their path, and 404 for everything else.
+pub fn serve(responses: Vec<Response>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(len) => request.extend_from_slice(&buffer[..len]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+            let path = request.split(' ').nth(1).unwrap_or_default();
+            let not_found = Response {
+                path: "",
+                status: 404,
+                content_type: "text/plain",
+                body: b"not found".to_vec(),
+                content_length: true,
+            };
+            let response = responses
+                .iter()
+                .find(|response| response.path == path)
+                .unwrap_or(&not_found);
+            let mut head = format!(
+                "HTTP/1.1 {} Status\r\nContent-Type: {}\r\nConnection: close\r\n",
+                response.status, response.content_type
+            );
+            if response.content_length {
+                head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+            }
+            head.push_str("\r\n");
+            let body = &response.body;
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(body);
+        }
+    });
+    url
+}
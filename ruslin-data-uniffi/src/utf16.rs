@@ -0,0 +1,33 @@
+/// Maps every UTF-8 byte offset of `s`, including `s.len()`, to its UTF-16 offset.
+pub fn utf8_to_utf16_offsets(s: &str) -> Vec<usize> {
+    let mut offsets: Vec<usize> = Vec::with_capacity(s.len() + 1);
+    let mut offset: usize = 0;
+    for c in s.chars() {
+        for _ in 0..c.len_utf8() {
+            offsets.push(offset);
+        }
+        offset += c.len_utf16();
+    }
+    offsets.push(offset);
+    offsets
+}
+
+/// Converts a UTF-16 offset of `s` to a byte offset. Returns `None` if the offset is out
+/// of range or points into the middle of a character.
+pub fn utf16_to_utf8_offset(s: &str, offset: usize) -> Option<usize> {
+    let mut utf16_offset: usize = 0;
+    for (index, c) in s.char_indices() {
+        if utf16_offset == offset {
+            return Some(index);
+        }
+        if utf16_offset > offset {
+            return None;
+        }
+        utf16_offset += c.len_utf16();
+    }
+    if utf16_offset == offset {
+        Some(s.len())
+    } else {
+        None
+    }
+}